@@ -1,154 +1,652 @@
-use crate::{configuration::FOREGROUND_COLOR, search::Search, select::Select, widgets::Widget};
-use freedesktop_desktop_entry::{default_paths, DesktopEntry, Iter};
-use log::debug;
+use crate::{
+    clipboard::{ClipboardRequest, Selection},
+    configuration::{Config, Mode},
+    error::Error,
+    history::History,
+    input::{Action, Keyboard, Route},
+    layout::{inset, prompt_height, stack, Direction, SPACING},
+    render::{Font, Renderer},
+    search::Search,
+    select::{EntryCallback, EntryState, Select},
+    theme::Theme,
+    widgets::{label::Label, Widget},
+};
+use freedesktop_desktop_entry::{default_paths, DesktopEntry, Iter, PathSource};
+use log::{debug, warn};
 use piston_window::*;
 use regex::Regex;
-use std::os::unix::process::CommandExt;
+use std::ffi::{OsStr, OsString};
+use std::io::BufRead;
+use std::os::unix::{fs::PermissionsExt, process::CommandExt};
+use std::path::PathBuf;
 use std::process::Command;
+use std::sync::OnceLock;
 use std::{collections::HashMap, iter::IntoIterator};
 
+// winit only takes a scale factor from the environment, so that's where window.scale
+// goes (see override_scale_factor).
+const SCALE_FACTOR_VAR: &str = "WINIT_X11_SCALE_FACTOR";
+
+// What SCALE_FACTOR_VAR was before we changed it, if we did. The programs we start get
+// this back, so they aren't scaled by our config.
+static INHERITED_SCALE_FACTOR: OnceLock<Option<OsString>> = OnceLock::new();
+
 pub struct ApplicationLauncher {
+    prompt: Label,
+    // The vi mode, next to the prompt.
+    mode_name: Label,
+    // What went wrong the last time something was picked, if anything. It takes the
+    // prompt's place until the next key or click.
+    error: Option<Error>,
+    error_label: Label,
     search: Search,
     select: Select,
+    keyboard: Keyboard,
+    // The searches that picked something, for Alt+P and Ctrl+R.
+    history: History,
+    // What main should do with the clipboard, since we don't have an X connection.
+    clipboard_request: Option<ClipboardRequest>,
+    // Set when Escape (or whatever cancel is bound to) should close the window.
+    closing: bool,
+    bounds: [f64; 4],
 }
 
 impl ApplicationLauncher {
-    pub fn new() -> Self {
-        // Basically copied from https://crates.io/crates/freedesktop-desktop-entry
-        let mut select_entries: HashMap<String, Box<dyn Fn() -> Result<(), String>>> =
-            HashMap::new();
-        // We want to iter through the local dirs last so they overwrite/override the system .desktop
-        // for users that want to overwrite the system .desktop entries. Using a HashMap helps us do this.
-        // The library already iters through the local directories first, so we are fine. Otherwise, we'd have to
-        // define our own custom list of directories to iter through.
-        //
-        // TODO handle this so that if they don't have a home directory, only use the system directories (the library
-        // panics if that happens).
-        //
-        // The Exec line of a .desktop entry has a few "field codes" that we aren't going to use (
-        // I think they're used for adding command line arguments for files/whatnot). You can see more
-        // details here (https://specifications.freedesktop.org/desktop-entry-spec/latest/ar01s07.html),
-        // but basically this regex pattern will take all those field codes that start with percent
-        // and a letter and replace them.
-        //
-        // We create the regex struct here so that we don't do it every time in the loop; that would
-        // be more inefficient.
-        let fieldcode_replace_regex = Regex::new("%(f|F|u|U|d|D|n|N|i|k|v|m)").expect(
-            "Programmer error in creating regex object to clean Exec line of desktop entry.",
-        );
+    // `filter` is what the search bar starts out with (--filter).
+    pub fn new(config: &Config, filter: Option<&str>) -> Self {
+        let entries = match config.mode {
+            Mode::Drun => desktop_entries(config),
+            Mode::Run => path_entries(),
+            Mode::Dmenu => stdin_entries(),
+        };
+        let history = History::load(config.mode, config.history_size);
+        Self::with_entries(config, filter, entries, history)
+    }
 
-        for (_, path) in Iter::new(default_paths()) {
-            debug!("path {:#?}", path);
-
-            if let Ok(bytes) = std::fs::read_to_string(&path) {
-                if let Ok(entry) = DesktopEntry::decode(&path, &bytes) {
-                    // We don't want duplicate desktop entries in here, but
-                    // we should override system desktop entries with the local ones.
-                    //
-                    // The library has a default order in which it loops through the desktop entry dirs,
-                    // and it does the local sources first, so we can just make it so that once it's done looping through
-
-                    //
-                    // TODO locale-changing? It wouldn't be too hard to implement. The
-                    // None that we put in entry.name/description is for specifying a locale.
-                    //
-                    // This variable chooses the string that you'll see in the select menu.
-                    let display_name = if let Some(entry_name) = entry.name(None) {
-                        entry_name
-                    }
-                    // Fall back to the application's appid, which should have enough
-                    // info to tell someone what they might be running.
-                    else {
-                        entry.appid
-                    };
-
-                    // If the .desktop file doesn't have an Exec field, we
-                    // can't launch it. We skip it.
-                    if entry.exec().is_none() {
-                        continue;
-                    }
+    // Like new, but with entries and a history from somewhere else, like the tests.
+    pub fn with_entries(
+        config: &Config,
+        filter: Option<&str>,
+        entries: Vec<(String, EntryCallback)>,
+        history: History,
+    ) -> Self {
+        let mut search = Search::new(config.vi_mode, &config.word_chars);
+        search.set_history(history.entries().to_vec());
+
+        let mut select = Select::new(entries);
+        select.configure(config);
+
+        let mut launcher = Self {
+            prompt: Label::new(config.prompt(), config.font.prompt_size),
+            // Smaller than the prompt, so it doesn't compete with it.
+            mode_name: Label::new(
+                search.mode_name().unwrap_or(""),
+                config.font.prompt_size / 2,
+            ),
+            error: None,
+            error_label: Label::error(config.font.size),
+            search,
+            select,
+            keyboard: Keyboard::new(config.keybindings.clone()),
+            history,
+            clipboard_request: None,
+            closing: false,
+            bounds: [0.0; 4],
+        };
+        if let Some(filter) = filter {
+            launcher.search.set_buffer(filter);
+            launcher.update_entry_filter();
+        }
+        launcher
+    }
+
+    pub fn set_entry_state(&mut self, indices: &[usize], state: EntryState) {
+        for &index in indices {
+            self.select.set_entry_state(index, state);
+        }
+    }
+
+    // For when the config is reloaded.
+    pub fn reconfigure(&mut self, config: &Config) {
+        self.keyboard.set_keybindings(config.keybindings.clone());
+        self.select.configure(config);
+        self.prompt.set_text(config.prompt());
+        self.prompt.set_size(config.font.prompt_size);
+        self.mode_name.set_size(config.font.prompt_size / 2);
+        self.error_label.set_size(config.font.size);
+        self.search.set_vi_mode(config.vi_mode);
+        self.search.set_word_chars(&config.word_chars);
+        self.mode_name
+            .set_text(self.search.mode_name().unwrap_or_default());
+        if config.history_size != self.history.limit() {
+            self.history.set_limit(config.mode, config.history_size);
+            self.search.set_history(self.history.entries().to_vec());
+        }
+    }
+
+    // Whatever should be done with the clipboard since the last call. main does the
+    // actual talking to X.
+    pub fn take_clipboard_request(&mut self) -> Option<ClipboardRequest> {
+        self.clipboard_request.take()
+    }
+
+    // Put text from the clipboard into the search bar.
+    pub fn paste(&mut self, text: &str) {
+        self.search.insert(text);
+        self.update_entry_filter();
+    }
+
+    // For --record and --replay, to tell whether a recording was made with the same
+    // entries.
+    pub fn entry_names(&self) -> impl Iterator<Item = &str> {
+        self.select.entries.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn wants_to_close(&self) -> bool {
+        self.closing
+    }
+
+    fn show_error(&mut self, error: Error) {
+        warn!("{}", error);
+        self.error_label.set_text(&error.to_string());
+        self.error = Some(error);
+    }
+
+    fn update_entry_filter(&mut self) {
+        if !self.search.buffer.is_empty() {
+            // Ew copy
+            self.select
+                .update_entry_filter(Some(self.search.buffer.clone()));
+        } else {
+            self.select.update_entry_filter(None);
+        }
+    }
+}
+
+// Where .desktop entries are looked for. default_paths panics without a home directory,
+// so then we only look in the system directories.
+fn desktop_entry_dirs() -> Vec<(PathSource, PathBuf)> {
+    if dirs::home_dir().is_some() {
+        return default_paths();
+    }
+    warn!("There's no home directory, so only the system's desktop entries are shown");
+    vec![
+        (
+            PathSource::SystemSnap,
+            PathBuf::from("/var/lib/snapd/desktop/applications"),
+        ),
+        (
+            PathSource::SystemFlatpak,
+            PathBuf::from("/var/lib/flatpak/exports/share/applications"),
+        ),
+        (PathSource::System, PathBuf::from("/usr/share/applications")),
+    ]
+}
 
-                    // We replace the field codes in the Exec field as described above.
-                    // TODO handle Exec fields with quotes in them.
-                    let exec_string = entry.exec().unwrap().to_owned();
-                    let exec_string = fieldcode_replace_regex
-                        .replace(&exec_string, "")
-                        .into_owned();
-
-                    select_entries.insert(
-                        display_name.to_string(),
-                        Box::new(move || {
-                            debug!("exec is {:?}", exec_string);
-                            // We are going to call execvp(3) using the nix crate
-                            // to replace this process with the application the user
-                            // selected.
-
-                            let mut exec_string = exec_string
-                                .split_whitespace()
-                                // TODO maybe don't escape unless the argument is surrounded in quotes? Anyway,
-                                // I'm too lazy to write my own unescape function, so we're using one from a library.
-                                .map(|arg| {
-                                    snailquote::unescape(arg).expect(
-                                        "Malformed desktop entry, failed to escape Exec argument",
-                                    )
-                                });
-
-                            Command::new(
-                                // If the expect/panic runs, that means the Exec is malformed (empty string?) We
-                                // probably *don't* want to panic on this (later), but I'm lazy.
-                                exec_string.next().expect(
-                                    ".desktop entry's Exec field is malformed (maybe blank)?",
-                                ),
-                            )
-                            .args(exec_string)
-                            .exec();
-
-                            // This will never happen… okay, maybe it will. Haha. That's why we can't panic!() here.
-                            Ok(())
-                        }),
-                    );
+// The entries for drun mode: every .desktop entry that we can launch.
+fn desktop_entries(config: &Config) -> Vec<(String, EntryCallback)> {
+    // Basically copied from https://crates.io/crates/freedesktop-desktop-entry
+    let mut select_entries: HashMap<String, EntryCallback> = HashMap::new();
+    // We want to iter through the local dirs last so they overwrite/override the system .desktop
+    // for users that want to overwrite the system .desktop entries. Using a HashMap helps us do this.
+    // The library already iters through the local directories first, so we are fine. Otherwise, we'd have to
+    // define our own custom list of directories to iter through.
+    //
+    // The Exec line of a .desktop entry has a few "field codes" that we aren't going to use (
+    // I think they're used for adding command line arguments for files/whatnot). You can see more
+    // details here (https://specifications.freedesktop.org/desktop-entry-spec/latest/ar01s07.html),
+    // but basically this regex pattern will take all those field codes that start with percent
+    // and a letter and replace them.
+    //
+    // We create the regex struct here so that we don't do it every time in the loop; that would
+    // be more inefficient.
+    let fieldcode_replace_regex = Regex::new("%(f|F|u|U|d|D|n|N|i|k|v|m)")
+        .expect("Programmer error in creating regex object to clean Exec line of desktop entry.");
+
+    for (_, path) in Iter::new(desktop_entry_dirs()) {
+        debug!("path {:#?}", path);
+
+        if let Ok(bytes) = std::fs::read_to_string(&path) {
+            if let Ok(entry) = DesktopEntry::decode(&path, &bytes) {
+                // We don't want duplicate desktop entries in here, but
+                // we should override system desktop entries with the local ones.
+                //
+                // The library has a default order in which it loops through the desktop entry dirs,
+                // and it does the local sources first, so we can just make it so that once it's done looping through
+
+                //
+                // TODO locale-changing? It wouldn't be too hard to implement. The
+                // None that we put in entry.name/description is for specifying a locale.
+                //
+                // This variable chooses the string that you'll see in the select menu.
+                let display_name = if let Some(entry_name) = entry.name(None) {
+                    entry_name
                 }
+                // Fall back to the application's appid, which should have enough
+                // info to tell someone what they might be running.
+                else {
+                    entry.appid
+                };
+
+                // If the .desktop file doesn't have an Exec field, we
+                // can't launch it. We skip it.
+                let exec_string = match entry.exec() {
+                    Some(exec_string) => exec_string,
+                    None => continue,
+                };
+
+                // We replace the field codes in the Exec field as described above.
+                // TODO handle Exec fields with quotes in them.
+                let exec_string = fieldcode_replace_regex
+                    .replace(exec_string, "")
+                    .into_owned();
+
+                // Applications like htop need a terminal to run in, so we run them with
+                // `<terminal> -e <Exec>`.
+                let exec_string = if entry.terminal() {
+                    format!("{} -e {}", config.terminal, exec_string)
+                } else {
+                    exec_string
+                };
+
+                let name = display_name.to_string();
+                select_entries.insert(
+                    name.clone(),
+                    Box::new(move || {
+                        debug!("exec is {:?}", exec_string);
+                        // A broken .desktop file is the file's fault, not ours, so
+                        // we say so in the window instead of crashing.
+                        let args = split_exec(&exec_string).ok_or_else(|| {
+                            Error::MalformedExec(name.clone(), exec_string.clone())
+                        })?;
+
+                        // We are going to call execvp(3) to replace this process with
+                        // the application the user selected.
+                        let exec_error = command(&args[0]).args(&args[1..]).exec();
+
+                        // exec only returns if it failed… okay, maybe it will. Haha. That's why we can't panic!() here.
+                        Err(Error::Launch(args[0].clone(), exec_error))
+                    }),
+                );
             }
         }
+    }
+
+    select_entries.into_iter().collect()
+}
+
+// Scale the window by `scale`, whatever winit would have worked out from the DPI. This
+// has to happen before the window is made.
+pub fn override_scale_factor(scale: f64) {
+    INHERITED_SCALE_FACTOR.get_or_init(|| std::env::var_os(SCALE_FACTOR_VAR));
+    std::env::set_var(SCALE_FACTOR_VAR, scale.to_string());
+}
+
+// A Command for something the user picked, with the environment we started with.
+fn command<S: AsRef<OsStr>>(program: S) -> Command {
+    command_with_scale_factor(program, INHERITED_SCALE_FACTOR.get())
+}
 
-        Self {
-            search: Search::new(),
-            select: Select::new(select_entries.into_iter().collect()),
+// command, with `inherited` in place of INHERITED_SCALE_FACTOR. None means we never
+// changed the scale factor, so there's nothing to put back.
+fn command_with_scale_factor<S: AsRef<OsStr>>(
+    program: S,
+    inherited: Option<&Option<OsString>>,
+) -> Command {
+    let mut command = Command::new(program);
+    match inherited {
+        Some(Some(inherited)) => {
+            command.env(SCALE_FACTOR_VAR, inherited);
         }
+        Some(None) => {
+            command.env_remove(SCALE_FACTOR_VAR);
+        }
+        None => {}
+    }
+    command
+}
+
+// Split an Exec line (with the field codes already taken out) into the program and its
+// arguments. None if there's no program, or an argument is quoted wrong.
+fn split_exec(exec_string: &str) -> Option<Vec<String>> {
+    let args = exec_string
+        .split_whitespace()
+        // TODO maybe don't escape unless the argument is surrounded in quotes? Anyway,
+        // I'm too lazy to write my own unescape function, so we're using one from a library.
+        .map(|arg| snailquote::unescape(arg).ok())
+        .collect::<Option<Vec<String>>>()?;
+    if args.is_empty() {
+        return None;
     }
+    Some(args)
+}
+
+// The entries for run mode: every executable in $PATH. Like with the desktop entries,
+// earlier directories in $PATH win when two of them have a program with the same name,
+// since that's the one the shell would run.
+fn path_entries() -> Vec<(String, EntryCallback)> {
+    let mut programs: HashMap<String, EntryCallback> = HashMap::new();
+    let path = std::env::var_os("PATH").unwrap_or_default();
+
+    for dir in std::env::split_paths(&path) {
+        let dir_entries = match std::fs::read_dir(&dir) {
+            Ok(dir_entries) => dir_entries,
+            // Directories in $PATH that don't exist aren't worth complaining about.
+            Err(_) => continue,
+        };
+
+        for dir_entry in dir_entries.flatten() {
+            let name = dir_entry.file_name().to_string_lossy().into_owned();
+            if programs.contains_key(&name) {
+                continue;
+            }
+            // fs::metadata follows symlinks, which is what we want since most of /usr/bin
+            // is symlinks on some distros.
+            let is_executable = std::fs::metadata(dir_entry.path())
+                .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false);
+            if !is_executable {
+                continue;
+            }
+
+            let program = dir_entry.path();
+            programs.insert(
+                name,
+                Box::new(move || {
+                    debug!("running {:?}", program);
+                    let exec_error = command(&program).exec();
+                    Err(Error::Launch(program.display().to_string(), exec_error))
+                }),
+            );
+        }
+    }
+
+    let mut programs: Vec<(String, EntryCallback)> = programs.into_iter().collect();
+    // A HashMap has no order, and looking for something in a randomly ordered list
+    // of a few thousand programs is no fun.
+    programs.sort_by(|(a, _), (b, _)| a.cmp(b));
+    programs
+}
+
+// The entries for dmenu mode: each line of stdin. Picking one prints it to stdout
+// and exits. Unlike the other modes we keep the order and duplicates, since whatever
+// is piping into us probably cares about those.
+fn stdin_entries() -> Vec<(String, EntryCallback)> {
+    std::io::stdin()
+        .lock()
+        .lines()
+        .map_while(Result::ok)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let label = line.clone();
+            let callback: EntryCallback = Box::new(move || {
+                println!("{}", line);
+                std::process::exit(0);
+            });
+            (label, callback)
+        })
+        .collect()
 }
 
 // Technically, this isn't a "widget," but it is a struct that renders other widgets.
 // … And some text.
 impl Widget for ApplicationLauncher {
-    fn draw<G>(&self, coords: [f64; 2], c: &Context, g: &mut G, glyph_cache: &mut Glyphs)
-    where
-        G: Graphics<Texture = Texture<gfx_device_gl::Resources>>,
-    {
-        text::Text::new_color(FOREGROUND_COLOR, 120)
-            .draw(
-                "Applications",
-                glyph_cache,
-                &DrawState::default(),
-                c.transform.trans(coords[0], coords[1]).zoom(0.5),
-                g,
-            )
-            .unwrap();
-        self.search
-            .draw([coords[0], coords[1] + 40.0], c, g, glyph_cache);
-        self.select
-            .draw([coords[0], coords[1] + 100.0], c, g, glyph_cache);
+    // The prompt, the search bar and the list on top of each other, with padding
+    // around them (see layout.rs). The list only asks for the rows that match, which
+    // is how main shrinks the window to fit them.
+    fn measure(&self, available: [f64; 2], config: &Config, font: &Font) -> [f64; 2] {
+        let padding = config.window.padding;
+        let content = [available[0] - 2.0 * padding, available[1] - 2.0 * padding];
+        let search_height = self.search.measure(content, config, font)[1];
+        let list_height = self.select.measure(content, config, font)[1];
+        let height = padding
+            + prompt_height(config)
+            + SPACING
+            + search_height
+            + SPACING
+            + list_height
+            + padding;
+        [available[0], height.min(available[1])]
     }
-    fn handle_event(&mut self, ev: &Event) {
-        self.search.handle_event(ev);
-        self.select.handle_event(ev);
 
-        if !self.search.buffer.is_empty() {
-            // Ew copy
-            self.select
-                .update_entry_filter(Some(self.search.buffer.clone()));
+    fn arrange(&mut self, rect: [f64; 4], config: &Config, font: &Font) {
+        self.bounds = rect;
+        let content = inset(rect, config.window.padding);
+        let available = [content[2], content[3]];
+
+        // The list gets whatever is left.
+        let search_height = self.search.measure(available, config, font)[1];
+        let rows = stack(
+            content,
+            Direction::Vertical,
+            &[prompt_height(config), search_height, f64::INFINITY],
+            SPACING,
+        );
+
+        // The prompt and the vi mode sit next to each other on the same baseline,
+        // which is prompt_size down from the top.
+        let mut prompt_row = rows[0];
+        prompt_row[3] = f64::from(config.font.prompt_size).min(prompt_row[3]);
+        let label_widths = [
+            self.prompt.measure(available, config, font)[0],
+            self.mode_name.measure(available, config, font)[0],
+        ];
+        let labels = stack(prompt_row, Direction::Horizontal, &label_widths, SPACING);
+
+        self.prompt.arrange(labels[0], config, font);
+        self.mode_name.arrange(labels[1], config, font);
+        // An error is in smaller text than the prompt, since it can be long, but it
+        // sits on the same baseline.
+        self.error_label.arrange(prompt_row, config, font);
+        self.search.arrange(rows[1], config, font);
+        self.select.arrange(rows[2], config, font);
+    }
+
+    fn bounds(&self) -> [f64; 4] {
+        self.bounds
+    }
+
+    fn draw<R: Renderer>(&self, config: &Config, theme: &Theme, renderer: &mut R) {
+        if self.error.is_some() {
+            // Anything too long for the window is cut off. The bits that hang below
+            // the baseline go in the spacing under the prompt, like the prompt's do.
+            let mut row = self.error_label.bounds();
+            row[3] += SPACING;
+            renderer.push_clip(row);
+            self.error_label.draw(config, theme, renderer);
+            renderer.pop_clip();
         } else {
-            self.select.update_entry_filter(None);
+            self.prompt.draw(config, theme, renderer);
+            self.mode_name.draw(config, theme, renderer);
+        }
+        self.search.draw(config, theme, renderer);
+        self.select.draw(config, theme, renderer);
+    }
+    fn handle_event(&mut self, ev: &Event) {
+        // The error goes away as soon as you do something else.
+        if ev.press_args().is_some() || ev.text_args().is_some() {
+            self.error = None;
+        }
+        match self.keyboard.route(ev) {
+            Route::Action(action) => self.handle_action(action),
+            Route::Widgets => {
+                // In normal mode, j and k go through the list like they would in vim.
+                if !self.search.insert_mode && !self.search.searching_history() {
+                    if let Event::Input(Input::Text(command), _) = ev {
+                        match command.as_str() {
+                            "j" => self.select.select_next(),
+                            "k" => self.select.select_previous(),
+                            _ => {}
+                        }
+                    }
+                }
+                self.search.handle_event(ev);
+                self.select.handle_event(ev);
+                // Clicking an entry runs it the same way Enter does.
+                if self.select.take_accept_request() {
+                    self.handle_action(Action::Accept);
+                }
+            }
+            Route::Nowhere => {}
         }
+        self.update_entry_filter();
+        self.mode_name
+            .set_text(self.search.mode_name().unwrap_or_default());
+    }
+
+    fn handle_action(&mut self, action: Action) {
+        // During a Ctrl+R search, the search gets the keys that edit or cancel it, and
+        // anything else ends the search first, like in readline. That way Enter runs
+        // what it found.
+        if self.search.searching_history() {
+            match action {
+                Action::HistorySearch | Action::DeleteBackward | Action::Cancel => {
+                    return self.search.handle_action(action)
+                }
+                _ => {
+                    self.search.finish_history_search();
+                    self.update_entry_filter();
+                }
+            }
+        }
+
+        match action {
+            // Escape closes the window, unless the search bar wants it for vi mode.
+            Action::Cancel if !self.search.captures_escape() => self.closing = true,
+            Action::PasteClipboard => {
+                self.clipboard_request = Some(ClipboardRequest::Paste(Selection::Clipboard))
+            }
+            Action::PastePrimary => {
+                self.clipboard_request = Some(ClipboardRequest::Paste(Selection::Primary))
+            }
+            Action::Copy => {
+                self.clipboard_request = self
+                    .select
+                    .selected_label()
+                    .map(|label| ClipboardRequest::Copy(label.to_string()))
+            }
+            Action::Accept => {
+                // Picking something usually execs, so the history has to be saved
+                // before that.
+                if self.select.selected_label().is_some() {
+                    self.history.add(&self.search.buffer);
+                    self.history.save();
+                }
+                if let Err(e) = self.select.accept() {
+                    self.show_error(e);
+                }
+            }
+            Action::MoveUp
+            | Action::MoveDown
+            | Action::PageUp
+            | Action::PageDown
+            | Action::FirstEntry
+            | Action::LastEntry => self.select.handle_action(action),
+            _ => self.search.handle_action(action),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splitting_exec_lines() {
+        assert_eq!(
+            split_exec("foot -e \"htop\""),
+            Some(vec![
+                "foot".to_string(),
+                "-e".to_string(),
+                "htop".to_string()
+            ])
+        );
+        // These used to panic when you picked them.
+        assert_eq!(split_exec("  "), None);
+        assert_eq!(split_exec("foot \"\\u{nope}\""), None);
+    }
+
+    #[test]
+    fn reloading_turns_vi_mode_on() {
+        use crate::configuration::ViMode;
+
+        let mut config = Config::default();
+        let history = History::load(config.mode, 0);
+        let mut launcher = ApplicationLauncher::with_entries(&config, None, Vec::new(), history);
+        assert_eq!(launcher.search.mode_name(), None);
+        config.vi_mode = ViMode::Normal;
+        launcher.reconfigure(&config);
+        assert_eq!(launcher.search.mode_name(), Some("-- NORMAL --"));
+        // Which mode it starts in only matters when it's turned on.
+        config.vi_mode = ViMode::Insert;
+        launcher.reconfigure(&config);
+        assert_eq!(launcher.search.mode_name(), Some("-- NORMAL --"));
+    }
+
+    #[test]
+    fn marking_entries_from_the_command_line() {
+        use crate::cli::Cli;
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from([
+            "wmcontroller",
+            "--dmenu",
+            "--urgent",
+            "0,2",
+            "--active",
+            "1,7",
+        ])
+        .unwrap();
+        assert_eq!(cli.urgent, [0, 2]);
+        assert_eq!(cli.active, [1, 7]);
+        // Only the long forms, so -a and -u are free for something else.
+        assert!(Cli::try_parse_from(["wmcontroller", "-u", "0"]).is_err());
+
+        let mut config = Config::default();
+        cli.apply(&mut config);
+        let entries = ["shutdown", "reboot", "suspend", "hibernate"]
+            .iter()
+            .map(|name| {
+                let callback: EntryCallback = Box::new(|| Ok(()));
+                (name.to_string(), callback)
+            })
+            .collect();
+        let history = History::load(config.mode, 0);
+        let mut launcher = ApplicationLauncher::with_entries(&config, None, entries, history);
+        // What main does.
+        launcher.set_entry_state(&cli.urgent, EntryState::Urgent);
+        launcher.set_entry_state(&cli.active, EntryState::Active);
+        let states: Vec<_> = (0..5).map(|i| launcher.select.entry_state(i)).collect();
+        assert_eq!(
+            states,
+            [
+                Some(EntryState::Urgent),
+                Some(EntryState::Active),
+                Some(EntryState::Urgent),
+                Some(EntryState::Normal),
+                // There is no 7, and that's fine.
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn launched_programs_keep_their_scale() {
+        let scale_factor = |command: &Command| {
+            command
+                .get_envs()
+                .find(|(name, _)| *name == SCALE_FACTOR_VAR)
+                .map(|(_, value)| value.map(OsStr::to_owned))
+        };
+
+        let inherited = Some(OsString::from("1.5"));
+        let launched = command_with_scale_factor("foot", Some(&inherited));
+        assert_eq!(scale_factor(&launched), Some(inherited));
+        // It wasn't set when we started, so it shouldn't be set for them either.
+        let launched = command_with_scale_factor("foot", Some(&None));
+        assert_eq!(scale_factor(&launched), Some(None));
+        // We never touched it, so whatever's there is theirs.
+        let launched = command_with_scale_factor("foot", None);
+        assert_eq!(scale_factor(&launched), None);
     }
 }
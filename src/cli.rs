@@ -0,0 +1,311 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Command line arguments. Everything in here that also exists in the config file
+// is layered on top of it: built-in defaults, then config.toml, then the command line.
+// That's why all of these are Options—None means "keep whatever the config says."
+
+use crate::configuration::{Anchor, Config, Mode, Monitor};
+use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Parser)]
+#[command(name = "wmcontroller", version, about)]
+pub struct Cli {
+    /// What to show
+    #[arg(long, value_enum)]
+    pub mode: Option<CliMode>,
+
+    /// Read the configuration from this file instead of
+    /// $XDG_CONFIG_HOME/wmcontroller/config.toml
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Use this theme (a name from --list-themes or a path to a theme file)
+    #[arg(long, value_name = "NAME")]
+    pub theme: Option<String>,
+
+    /// Print the names of the themes that --theme accepts and exit
+    #[arg(long)]
+    pub list_themes: bool,
+
+    /// Window width in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: Option<u32>,
+
+    /// Window height in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub height: Option<u32>,
+
+    /// Number of entries to show at once
+    #[arg(long, value_parser = parse_lines)]
+    pub lines: Option<usize>,
+
+    /// Text to show above the search bar
+    #[arg(long)]
+    pub prompt: Option<String>,
+
+    /// Font as a fontconfig pattern, optionally with a size in points, like
+    /// "JetBrains Mono-12:bold" or "JetBrains Mono:bold:size=12"
+    #[arg(long, value_name = "FONT")]
+    pub font: Option<String>,
+
+    /// Scale everything by this much instead of going by the DPI
+    #[arg(long, value_parser = parse_scale)]
+    pub scale: Option<f64>,
+
+    /// Read entries from stdin and print the selected one (same as --mode dmenu)
+    #[arg(long, conflicts_with = "mode")]
+    pub dmenu: bool,
+
+    /// In dmenu mode, show these (zero-based) lines as urgent
+    #[arg(long, value_name = "ROWS", value_delimiter = ',')]
+    pub urgent: Vec<usize>,
+
+    /// In dmenu mode, show these (zero-based) lines as active
+    #[arg(long, value_name = "ROWS", value_delimiter = ',')]
+    pub active: Vec<usize>,
+
+    /// Start with this text in the search bar
+    #[arg(long, value_name = "TEXT")]
+    pub filter: Option<String>,
+
+    /// Monitor to open on: "focused", "pointer", "primary" or an output name like "DP-1"
+    #[arg(long, value_name = "MONITOR")]
+    pub monitor: Option<Monitor>,
+
+    /// Where on the monitor to put the window
+    #[arg(long, value_enum)]
+    pub anchor: Option<CliAnchor>,
+
+    /// Print the configuration that would be used and exit
+    #[arg(long)]
+    pub dump_config: bool,
+
+    /// Write the keyboard and mouse input to this file, for bug reports
+    #[arg(long, value_name = "PATH")]
+    pub record: Option<PathBuf>,
+
+    /// Play back input written by --record instead of taking it from the keyboard
+    /// and mouse
+    #[arg(long, value_name = "PATH", conflicts_with = "record")]
+    pub replay: Option<PathBuf>,
+
+    // Copying starts another one of us with this to own the clipboard, and writes
+    // what to copy to its stdin (see clipboard::copy). It's not something you'd run
+    // yourself, so it's hidden.
+    #[arg(long, hide = true)]
+    pub serve_clipboard: bool,
+}
+
+// Mode lives in configuration.rs, which shouldn't need to know about clap, so we
+// mirror it here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CliMode {
+    Drun,
+    Run,
+    Dmenu,
+}
+
+impl From<CliMode> for Mode {
+    fn from(mode: CliMode) -> Self {
+        match mode {
+            CliMode::Drun => Mode::Drun,
+            CliMode::Run => Mode::Run,
+            CliMode::Dmenu => Mode::Dmenu,
+        }
+    }
+}
+
+// Same as CliMode, for Anchor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CliAnchor {
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl From<CliAnchor> for Anchor {
+    fn from(anchor: CliAnchor) -> Self {
+        match anchor {
+            CliAnchor::Center => Anchor::Center,
+            CliAnchor::Top => Anchor::Top,
+            CliAnchor::Bottom => Anchor::Bottom,
+            CliAnchor::Left => Anchor::Left,
+            CliAnchor::Right => Anchor::Right,
+            CliAnchor::TopLeft => Anchor::TopLeft,
+            CliAnchor::TopRight => Anchor::TopRight,
+            CliAnchor::BottomLeft => Anchor::BottomLeft,
+            CliAnchor::BottomRight => Anchor::BottomRight,
+        }
+    }
+}
+
+fn parse_lines(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("must be greater than zero".to_string()),
+        Ok(lines) => Ok(lines),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_scale(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(scale) if scale > 0.0 && scale.is_finite() => Ok(scale),
+        Ok(_) => Err("must be greater than zero".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+impl Cli {
+    // Overwrite the parts of the config that were given on the command line.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(mode) = self.mode {
+            config.mode = mode.into();
+        }
+        if self.dmenu {
+            config.mode = Mode::Dmenu;
+        }
+        if let Some(ref theme) = self.theme {
+            config.theme = theme.clone();
+        }
+        if let Some(width) = self.width {
+            config.window.width = width;
+        }
+        if let Some(height) = self.height {
+            config.window.height = height;
+        }
+        if let Some(lines) = self.lines {
+            config.lines = lines;
+        }
+        if let Some(ref prompt) = self.prompt {
+            config.prompt = Some(prompt.clone());
+        }
+        if let Some(ref monitor) = self.monitor {
+            config.monitor = monitor.clone();
+        }
+        if let Some(anchor) = self.anchor {
+            config.window.anchor = anchor.into();
+        }
+        if let Some(ref font) = self.font {
+            config.font.set_family(font);
+        }
+        if let Some(scale) = self.scale {
+            config.window.scale = Some(scale);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::DEFAULT_LINES;
+
+    fn layered(config_file: &str, args: &[&str]) -> Config {
+        let mut config = Config::parse(config_file).unwrap();
+        let cli = Cli::try_parse_from(std::iter::once("wmcontroller").chain(args.iter().copied()))
+            .unwrap();
+        cli.apply(&mut config);
+        config
+    }
+
+    #[test]
+    fn defaults_without_config_or_arguments() {
+        assert_eq!(layered("", &[]), Config::default());
+    }
+
+    #[test]
+    fn config_file_overrides_defaults() {
+        let config = layered("lines = 3\n[window]\nwidth = 640\n", &[]);
+        assert_eq!(config.lines, 3);
+        assert_eq!(config.window.width, 640);
+        // Things the config file doesn't mention keep their defaults.
+        assert_eq!(config.window.height, Config::default().window.height);
+    }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let config = layered(
+            "lines = 3\nmode = \"run\"\nprompt = \"from config\"\ntheme = \"dark\"\n[window]\nwidth = 640\n",
+            &["--lines", "10", "--prompt", "from cli", "--mode", "drun", "--theme", "light"],
+        );
+        assert_eq!(config.lines, 10);
+        assert_eq!(config.prompt(), "from cli");
+        assert_eq!(config.mode, Mode::Drun);
+        assert_eq!(config.theme, "light");
+        // --width wasn't given, so the config file still wins over the default.
+        assert_eq!(config.window.width, 640);
+    }
+
+    #[test]
+    fn command_line_overrides_defaults() {
+        let config = layered("", &["--height", "200", "--monitor", "DP-1"]);
+        assert_eq!(config.window.height, 200);
+        assert_eq!(config.monitor, Monitor::Named("DP-1".to_string()));
+        assert_eq!(config.lines, DEFAULT_LINES);
+    }
+
+    #[test]
+    fn dmenu_flag_sets_mode() {
+        let config = layered("mode = \"run\"\n", &["--dmenu"]);
+        assert_eq!(config.mode, Mode::Dmenu);
+        assert_eq!(config.prompt(), "Select");
+    }
+
+    #[test]
+    fn zero_lines_is_rejected() {
+        assert!(Cli::try_parse_from(["wmcontroller", "--lines", "0"]).is_err());
+        assert!(Config::parse("lines = 0\n").is_err());
+    }
+
+    #[test]
+    fn font_patterns_with_sizes() {
+        let config = layered("[font]\nfamily = \"JetBrains Mono-12:bold\"\n", &[]);
+        assert_eq!(config.font.family, "JetBrains Mono:bold");
+        assert_eq!(config.font.size, 12);
+        // Without a size in the pattern, `size` stays what it was.
+        let config = layered("[font]\nsize = 14\n", &["--font", "monospace:italic"]);
+        assert_eq!(config.font.family, "monospace:italic");
+        assert_eq!(config.font.size, 14);
+        let config = layered("", &["--font", "Fira Code:size=10", "--scale", "2"]);
+        assert_eq!(config.font.family, "Fira Code");
+        assert_eq!(config.font.size, 10);
+        assert_eq!(config.window.scale, Some(2.0));
+    }
+
+    #[test]
+    fn scale_has_to_be_positive() {
+        assert!(Cli::try_parse_from(["wmcontroller", "--scale", "0"]).is_err());
+        assert!(Config::parse("[window]\nscale = -1.0\n").is_err());
+        assert_eq!(
+            Config::parse("[window]\nscale = 1.5\n")
+                .unwrap()
+                .window
+                .scale,
+            Some(1.5)
+        );
+    }
+
+    #[test]
+    fn placing_the_window() {
+        let config = layered(
+            "monitor = \"pointer\"\n[window]\nanchor = \"top-left\"\nx_offset = 20\ny_offset = -10\n",
+            &[],
+        );
+        assert_eq!(config.monitor, Monitor::Pointer);
+        assert_eq!(config.window.anchor, Anchor::TopLeft);
+        assert_eq!((config.window.x_offset, config.window.y_offset), (20, -10));
+
+        let config = layered(
+            "[window]\nanchor = \"top-left\"\n",
+            &["--anchor", "bottom-right"],
+        );
+        assert_eq!(config.window.anchor, Anchor::BottomRight);
+        assert!(Config::parse("[window]\nanchor = \"middle\"\n").is_err());
+    }
+}
@@ -0,0 +1,433 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Pasting from and copying to the X selections: CLIPBOARD (what Ctrl+C/Ctrl+V use
+// everywhere) and PRIMARY (whatever you last highlighted, which Shift+Insert pastes).
+//
+// X doesn't keep the contents of a selection anywhere. Pasting means asking whoever
+// owns it to convert it and put the result in a property on one of our windows, and
+// copying means becoming the owner and answering those requests from everyone else.
+// The launcher closes as soon as you pick something, and the clipboard would go with
+// it, so copying hands the text to a second WMController process that keeps owning
+// the clipboard until another app takes it (see copy and serve).
+
+use crate::xutil::{get_property, intern_atom, root_window};
+use log::{debug, warn};
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::os::raw::{c_int, c_long, c_ulong};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use winit::platform::unix::x11::{ffi, XConnection};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    Clipboard,
+    Primary,
+}
+
+impl Selection {
+    fn atom(self, xconn: &XConnection) -> ffi::Atom {
+        match self {
+            Selection::Clipboard => intern_atom(xconn, "CLIPBOARD"),
+            Selection::Primary => ffi::XA_PRIMARY,
+        }
+    }
+}
+
+// What the widgets ask main to do, since they don't have an X connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardRequest {
+    Paste(Selection),
+    Copy(String),
+}
+
+// How long we wait on the owner of a selection before giving up on a paste. It's
+// usually a couple of milliseconds, but an app that's hung would hang us too.
+const PASTE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// The property on our window that selection owners put the text in.
+const PASTE_PROPERTY: &str = "WMCONTROLLER_SELECTION";
+
+// Get the text in a selection, if there is any and its owner answers in time.
+//
+// This uses winit's connection. winit only reads events off of it while we're waiting
+// for the next piston event, so as long as we wait for the answer right here, the
+// events we're waiting for can't be taken by winit first.
+pub fn paste(xconn: &XConnection, selection: Selection) -> Option<String> {
+    // The owner puts the text on a window of ours. We make a throwaway one instead of
+    // using the launcher window, since we need PropertyChangeMask for INCR transfers
+    // and changing the launcher window's event mask would mess with winit.
+    let window = unsafe {
+        let window = (xconn.xlib.XCreateSimpleWindow)(
+            xconn.display,
+            root_window(xconn),
+            0,
+            0,
+            1,
+            1,
+            0,
+            0,
+            0,
+        );
+        (xconn.xlib.XSelectInput)(xconn.display, window, ffi::PropertyChangeMask);
+        window
+    };
+    let text = convert_selection(xconn, window, selection.atom(xconn));
+    unsafe {
+        (xconn.xlib.XDestroyWindow)(xconn.display, window);
+        (xconn.xlib.XFlush)(xconn.display);
+    }
+    text
+}
+
+fn convert_selection(
+    xconn: &XConnection,
+    window: ffi::Window,
+    selection: ffi::Atom,
+) -> Option<String> {
+    let property = intern_atom(xconn, PASTE_PROPERTY);
+    let utf8_string = intern_atom(xconn, "UTF8_STRING");
+    let incr = intern_atom(xconn, "INCR");
+
+    // We'd rather have UTF-8, but some old apps only do Latin-1 STRINGs.
+    for target in [utf8_string, ffi::XA_STRING] {
+        let event = unsafe {
+            (xconn.xlib.XConvertSelection)(
+                xconn.display,
+                selection,
+                target,
+                property,
+                window,
+                ffi::CurrentTime,
+            );
+            wait_for_event(xconn, window, ffi::SelectionNotify, |_| true)?
+        };
+        let notify = ffi::XSelectionEvent::from(&event);
+        // No property means the owner couldn't convert to this target (or there's no
+        // owner at all, in which case the next target won't work either, but it's
+        // cheap to find out).
+        if notify.property == 0 {
+            continue;
+        }
+
+        // Writing the property on our window queued a PropertyNotify for it, which
+        // read_incr would otherwise take for the first chunk.
+        discard_events(xconn, window, ffi::PropertyNotify);
+        let (actual_type, _, bytes) = get_property(
+            xconn,
+            window,
+            property,
+            ffi::AnyPropertyType as ffi::Atom,
+            true,
+        )?;
+        let bytes = if actual_type == incr {
+            read_incr(xconn, window, property)?
+        } else {
+            bytes
+        };
+
+        return Some(if target == utf8_string {
+            String::from_utf8_lossy(&bytes).into_owned()
+        } else {
+            // Latin-1 maps straight onto the first 256 code points.
+            bytes.iter().map(|&byte| byte as char).collect()
+        });
+    }
+    None
+}
+
+// Big selections come in chunks with INCR: deleting the property (which get_property
+// already did) asks for the next chunk, and an empty chunk means that was all of it.
+fn read_incr(xconn: &XConnection, window: ffi::Window, property: ffi::Atom) -> Option<Vec<u8>> {
+    debug!("Reading the selection in chunks");
+    let mut bytes = Vec::new();
+    loop {
+        wait_for_event(xconn, window, ffi::PropertyNotify, |event| {
+            let event = ffi::XPropertyEvent::from(event);
+            event.atom == property && event.state == ffi::PropertyNewValue
+        })?;
+        // A notification can still be for a chunk we've already read and deleted, so
+        // no property yet just means the next one isn't there yet.
+        let chunk = match get_property(
+            xconn,
+            window,
+            property,
+            ffi::AnyPropertyType as ffi::Atom,
+            true,
+        ) {
+            Some((_, _, chunk)) => chunk,
+            None => continue,
+        };
+        if chunk.is_empty() {
+            return Some(bytes);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+}
+
+// Throw away the events of `event_type` on `window` that are already queued.
+fn discard_events(xconn: &XConnection, window: ffi::Window, event_type: c_int) {
+    let mut event: ffi::XEvent = unsafe { std::mem::zeroed() };
+    while unsafe {
+        (xconn.xlib.XCheckTypedWindowEvent)(xconn.display, window, event_type, &mut event)
+    } != 0
+    {}
+}
+
+// Wait (up to PASTE_TIMEOUT) for an event of `event_type` on `window` that `wanted`
+// is happy with. Events that it isn't happy with are dropped.
+fn wait_for_event<F>(
+    xconn: &XConnection,
+    window: ffi::Window,
+    event_type: c_int,
+    mut wanted: F,
+) -> Option<ffi::XEvent>
+where
+    F: FnMut(&ffi::XEvent) -> bool,
+{
+    let start = Instant::now();
+    unsafe { (xconn.xlib.XFlush)(xconn.display) };
+    loop {
+        let mut event: ffi::XEvent = unsafe { std::mem::zeroed() };
+        let found = unsafe {
+            (xconn.xlib.XCheckTypedWindowEvent)(xconn.display, window, event_type, &mut event)
+        };
+        if found != 0 {
+            if wanted(&event) {
+                return Some(event);
+            }
+            continue;
+        }
+        if start.elapsed() > PASTE_TIMEOUT {
+            warn!("Gave up waiting on the selection owner");
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+// Put `text` on the clipboard. We start `wmcontroller --serve-clipboard` to own it, so
+// it's still there after we close, and hand it the text on its stdin. Not as an
+// argument: anyone can read those in ps, there's a limit on how long they can be, and
+// they can't have a NUL in them.
+pub fn copy(text: &str) {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            warn!("Can't copy, failed to find our own executable: {}", e);
+            return;
+        }
+    };
+    let spawned = Command::new(exe)
+        .arg("--serve-clipboard")
+        .stdin(Stdio::piped())
+        // In dmenu mode our stdout is somebody's pipe, and it shouldn't stay open
+        // until someone else copies something.
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Failed to start the clipboard owner: {}", e);
+            return;
+        }
+    };
+    // Dropping stdin once it's written closes it, which is how the other end knows
+    // that's all of it.
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Err(e) = stdin.write_all(text.as_bytes()) {
+            warn!("Failed to hand the clipboard owner what to copy: {}", e);
+        }
+    }
+    // It can stop owning the clipboard while we're still open, and it'd be a zombie
+    // until we close if nobody waited on it. If we close first, init adopts it.
+    std::thread::spawn(move || {
+        if let Err(e) = child.wait() {
+            warn!("Failed to wait on the clipboard owner: {}", e);
+        }
+    });
+}
+
+// The other half of copy: own the clipboard with what's on stdin in it and answer
+// everyone who asks for it, until someone else owns the clipboard. This runs in its
+// own process with its own connection, since there's no winit in it.
+pub fn serve() {
+    // The default error handler exits the process, and a requestor's window going
+    // away in the middle of a request shouldn't take the clipboard with it.
+    unsafe extern "C" fn ignore_x_errors(
+        _display: *mut ffi::Display,
+        _event: *mut ffi::XErrorEvent,
+    ) -> c_int {
+        0
+    }
+
+    let mut text = String::new();
+    if let Err(e) = std::io::stdin().read_to_string(&mut text) {
+        warn!("Failed to read what to put on the clipboard: {}", e);
+        return;
+    }
+
+    let xconn = match XConnection::new(Some(ignore_x_errors)) {
+        Ok(xconn) => xconn,
+        Err(e) => {
+            warn!(
+                "Failed to connect to the X server to own the clipboard: {:?}",
+                e
+            );
+            return;
+        }
+    };
+    let clipboard = Selection::Clipboard.atom(&xconn);
+    let window = unsafe {
+        let window = (xconn.xlib.XCreateSimpleWindow)(
+            xconn.display,
+            root_window(&xconn),
+            0,
+            0,
+            1,
+            1,
+            0,
+            0,
+            0,
+        );
+        (xconn.xlib.XSelectInput)(xconn.display, window, ffi::PropertyChangeMask);
+        window
+    };
+    // The ICCCM doesn't allow taking a selection at CurrentTime, since then nobody can
+    // tell which of two owners came first.
+    let time = server_time(&xconn, window);
+    unsafe { (xconn.xlib.XSetSelectionOwner)(xconn.display, clipboard, window, time) };
+    if unsafe { (xconn.xlib.XGetSelectionOwner)(xconn.display, clipboard) } != window {
+        warn!("Failed to take ownership of the clipboard");
+        return;
+    }
+
+    loop {
+        let mut event: ffi::XEvent = unsafe { std::mem::zeroed() };
+        unsafe { (xconn.xlib.XNextEvent)(xconn.display, &mut event) };
+        match event.get_type() {
+            ffi::SelectionClear => {
+                debug!("Someone else owns the clipboard now");
+                return;
+            }
+            ffi::SelectionRequest => {
+                let request = ffi::XSelectionRequestEvent::from(&event);
+                answer_request(&xconn, &request, &text);
+            }
+            _ => {}
+        }
+    }
+}
+
+// What time it is on the X server, which is only ever handed out with events. Appending
+// nothing to a property on `window` gets us a PropertyNotify with the time on it without
+// changing anything. `window` has to have PropertyChangeMask selected.
+fn server_time(xconn: &XConnection, window: ffi::Window) -> ffi::Time {
+    let property = intern_atom(xconn, "WMCONTROLLER_TIMESTAMP");
+    let mut event: ffi::XEvent = unsafe { std::mem::zeroed() };
+    unsafe {
+        (xconn.xlib.XChangeProperty)(
+            xconn.display,
+            window,
+            property,
+            ffi::XA_STRING,
+            8,
+            ffi::PropModeAppend,
+            [].as_ptr(),
+            0,
+        );
+        (xconn.xlib.XWindowEvent)(xconn.display, window, ffi::PropertyChangeMask, &mut event);
+    }
+    ffi::XPropertyEvent::from(&event).time
+}
+
+fn answer_request(xconn: &XConnection, request: &ffi::XSelectionRequestEvent, text: &str) {
+    let targets = intern_atom(xconn, "TARGETS");
+    let utf8_string = intern_atom(xconn, "UTF8_STRING");
+    let text_atom = intern_atom(xconn, "TEXT");
+    let text_plain = intern_atom(xconn, "text/plain;charset=utf-8");
+
+    // Really old clients don't set a property and expect us to use the target's name.
+    let property = if request.property == 0 {
+        request.target
+    } else {
+        request.property
+    };
+
+    // Labels are way smaller than the largest request X allows, so we never have to
+    // send anything with INCR ourselves.
+    let converted = unsafe {
+        if request.target == targets {
+            let supported: [c_ulong; 5] =
+                [targets, utf8_string, text_plain, text_atom, ffi::XA_STRING];
+            (xconn.xlib.XChangeProperty)(
+                xconn.display,
+                request.requestor,
+                property,
+                ffi::XA_ATOM,
+                32,
+                ffi::PropModeReplace,
+                supported.as_ptr() as *const u8,
+                supported.len() as c_int,
+            );
+            true
+        } else if [utf8_string, text_plain, text_atom].contains(&request.target) {
+            (xconn.xlib.XChangeProperty)(
+                xconn.display,
+                request.requestor,
+                property,
+                utf8_string,
+                8,
+                ffi::PropModeReplace,
+                text.as_ptr(),
+                text.len() as c_int,
+            );
+            true
+        } else if request.target == ffi::XA_STRING {
+            // STRING is Latin-1, so anything that doesn't fit becomes a question mark.
+            let latin1: Vec<u8> = text
+                .chars()
+                .map(|ch| u8::try_from(u32::from(ch)).unwrap_or(b'?'))
+                .collect();
+            (xconn.xlib.XChangeProperty)(
+                xconn.display,
+                request.requestor,
+                property,
+                ffi::XA_STRING,
+                8,
+                ffi::PropModeReplace,
+                latin1.as_ptr(),
+                latin1.len() as c_int,
+            );
+            true
+        } else {
+            false
+        }
+    };
+
+    let mut notify = ffi::XEvent {
+        selection: ffi::XSelectionEvent {
+            type_: ffi::SelectionNotify,
+            serial: 0,
+            send_event: ffi::True,
+            display: xconn.display,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            // Telling them there's no property is how we say we can't convert to that.
+            property: if converted { property } else { 0 },
+            time: request.time,
+        },
+    };
+    unsafe {
+        (xconn.xlib.XSendEvent)(
+            xconn.display,
+            request.requestor,
+            ffi::False,
+            0 as c_long,
+            &mut notify,
+        );
+        (xconn.xlib.XFlush)(xconn.display);
+    }
+}
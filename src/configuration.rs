@@ -1,15 +1,746 @@
-/* COLORS */
+/* SPDX-License-Identifier: Zlib */
 
-// Background color of window. It's also used when for the text color in the select
-// widget
-pub const BACKGROUND_COLOR: [f32; 4] = [18.0 / 255.0, 69.0 / 255.0, 81.0 / 255.0, 1.0];
-// The foreground color is the color of the borders of the rectangles and the
-// text color.
-pub const FOREGROUND_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+// Everything that used to be a compile-time constant in here now lives in
+// $XDG_CONFIG_HOME/wmcontroller/config.toml. The defaults below are what you get
+// if that file doesn't exist (or if it leaves a key out). Most of them are the values
+// that used to be hard-coded, but not all: the search bar and the list used to have
+// their own font sizes (20, and whatever fit the list's rows), and now they share one.
+//
+// A full config file looks like this:
+//
+//     lines = 7
+//     terminal = "alacritty"
+//     mode = "drun"
+//     prompt = "Applications"
+//     theme = "default"
+//     monitor = "focused"
+//     vi_mode = "off"
+//     word_chars = ""
+//     history_size = 100
+//     mouse_accept = "double-click"
+//     scroll_mode = "page"
+//     scroll_margin = 2
+//     wrap_around = false
+//     ellipsis = "end"
+//
+//     [colors]
+//     background = "#124551"
+//     foreground = "#ffffff"
+//
+//     [keybindings]
+//     move-up = ["up", "ctrl+p"]
+//
+//     [palette]
+//     color4 = "#268bd2"
+//
+//     [font]
+//     family = "JetBrains Mono"
+//     size = 18
+//     prompt_size = 60
+//
+//     [window]
+//     width = 800
+//     height = 500
+//     padding = 40.0
+//     shrink = false
+//     scale = 1.0
+//     anchor = "center"
+//     x_offset = 0
+//     y_offset = 0
+//
+// Sizes are in points for text and in pixels for everything else, on a 96 DPI screen.
+// On a screen with more DPI (going by Xft.dpi, or the monitor if that isn't set),
+// everything is scaled up to match, unless window.scale says how much to scale by.
+
+use crate::input::Keybindings;
+use crate::palette::Palette;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 /* FONT DETAILS  */
 
 // You can change the font to whatever you desire.
 // You can also specify font famlies, like just
-// specifying "monospace." as your FONT_NAME
-pub const FONT_NAME: &str = "JetBrains Mono";
+// specifying "monospace." as your font family
+pub const DEFAULT_FONT_NAME: &str = "JetBrains Mono";
+
+// This is the size of the text in the search bar and the select list. The search bar
+// used to be a little bigger, at 20.
+pub const DEFAULT_FONT_SIZE: u32 = 18;
+// And this is the size of the prompt above them.
+pub const DEFAULT_PROMPT_FONT_SIZE: u32 = 60;
+
+/* LAYOUT */
+
+pub const DEFAULT_WINDOW_WIDTH: u32 = 800;
+pub const DEFAULT_WINDOW_HEIGHT: u32 = 500;
+// The space between the edge of the window and everything in it (see layout.rs).
+pub const DEFAULT_PADDING: f64 = 40.0;
+// How many entries the select list shows at a time.
+pub const DEFAULT_LINES: usize = 7;
+
+/* MISC */
+
+pub const DEFAULT_THEME: &str = "default";
+
+pub const DEFAULT_HISTORY_SIZE: usize = 100;
+
+pub const DEFAULT_SCROLL_MARGIN: usize = 2;
+
+// Desktop entries with Terminal=true get run inside this.
+pub const DEFAULT_TERMINAL: &str = "xterm";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub font: Font,
+    pub window: Window,
+    // The maximum number of entries the select list shows before it starts scrolling.
+    #[serde(deserialize_with = "deserialize_nonzero")]
+    pub lines: usize,
+    // The terminal emulator that is used to launch applications that need one.
+    // It is run as `<terminal> -e <command>`, which pretty much every terminal supports.
+    pub terminal: String,
+    // What the launcher shows when it starts.
+    pub mode: Mode,
+    // The text above the search bar. If it isn't set, each mode picks its own.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    // The name of the theme that decides the colors and such (see theme.rs).
+    pub theme: String,
+    // The background and foreground to use instead of the theme's own.
+    #[serde(skip_serializing_if = "Colors::is_empty")]
+    pub colors: Colors,
+    // Colors for the "xresources" and "pywal" themes to use when Xresources or
+    // pywal don't have them (see palette.rs).
+    #[serde(skip_serializing_if = "Palette::is_empty")]
+    pub palette: Palette,
+    // Which monitor the window opens on.
+    pub monitor: Monitor,
+    // Vi-style modal editing in the search bar, and which mode it starts in.
+    pub vi_mode: ViMode,
+    // Characters that Alt+B, Alt+F and Ctrl+Backspace treat as part of a word, on top
+    // of letters and digits. Like WORDCHARS in zsh.
+    pub word_chars: String,
+    // How many of the searches you picked something with are remembered for each
+    // mode (see history.rs). Zero turns the history off.
+    pub history_size: usize,
+    // Whether clicking an entry once runs it, or it takes a double click.
+    pub mouse_accept: MouseAccept,
+    // Whether the list moves a page or a row at a time when the selection goes off of it.
+    pub scroll_mode: ScrollMode,
+    // With smooth scrolling, how many rows the list keeps on screen above and below
+    // the selection.
+    pub scroll_margin: usize,
+    // Whether going down from the last entry goes to the first one, and up from the
+    // first one to the last one.
+    pub wrap_around: bool,
+    // Where entries too long for the list are cut short.
+    pub ellipsis: Ellipsis,
+    // Changes to the default keybindings (see input.rs).
+    #[serde(skip_serializing_if = "Keybindings::is_default")]
+    pub keybindings: Keybindings,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Font {
+    // This is handed straight to fontconfig, so anything fc-match understands works,
+    // like "JetBrains Mono:bold:italic". A size the way fontconfig writes one
+    // ("JetBrains Mono-12:bold" or "JetBrains Mono:bold:size=12") takes the place of
+    // `size`.
+    pub family: String,
+    // In points.
+    #[serde(deserialize_with = "deserialize_nonzero")]
+    pub size: u32,
+    #[serde(deserialize_with = "deserialize_nonzero")]
+    pub prompt_size: u32,
+}
+
+// The two colors everything used to be drawn in. Each one that's set takes the place
+// of the theme's own everywhere that color was used (see Theme::with_colors).
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<Color>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Window {
+    #[serde(deserialize_with = "deserialize_nonzero")]
+    pub width: u32,
+    #[serde(deserialize_with = "deserialize_nonzero")]
+    pub height: u32,
+    #[serde(deserialize_with = "deserialize_padding")]
+    pub padding: f64,
+    // Make the window only as tall as the rows that are showing, instead of always
+    // `height` tall. `height` is still as tall as it gets.
+    pub shrink: bool,
+    // How many pixels on screen each of our pixels takes up. If it isn't set, it comes
+    // from the DPI. This is only read at startup.
+    #[serde(
+        deserialize_with = "deserialize_scale",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub scale: Option<f64>,
+    // Where on the monitor the window goes, and then how far to move it from there
+    // (right and down), in pixels.
+    pub anchor: Anchor,
+    pub x_offset: i32,
+    pub y_offset: i32,
+}
+
+// The part of the monitor the window is put against. The window's own corner or edge
+// goes against the monitor's, so "bottom-right" puts it in the bottom right corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    #[default]
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    // Launch applications from .desktop entries, like `rofi -show drun`.
+    #[default]
+    Drun,
+    // Run any executable in $PATH, like `rofi -show run`.
+    Run,
+    // Pick one of the lines on stdin and print it to stdout, like dmenu.
+    Dmenu,
+}
+
+impl Mode {
+    // The name it has in the config file and on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Drun => "drun",
+            Mode::Run => "run",
+            Mode::Dmenu => "dmenu",
+        }
+    }
+
+    // The prompt we show when the user hasn't set one.
+    pub fn default_prompt(self) -> &'static str {
+        match self {
+            Mode::Drun => "Applications",
+            Mode::Run => "Run",
+            Mode::Dmenu => "Select",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ViMode {
+    // No modes, you're always typing into the search bar and Escape closes the window.
+    #[default]
+    Off,
+    // Start out typing, and Escape goes to normal mode.
+    Insert,
+    // Start out in normal mode, where keys are commands.
+    Normal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MouseAccept {
+    // The first click on an entry selects it, and a second one runs it.
+    #[default]
+    DoubleClick,
+    Click,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ellipsis {
+    // "a very long na…"
+    #[default]
+    End,
+    // "a very…g name", which keeps the end of things like paths.
+    Middle,
+    // Just cut off at the edge of the list.
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrollMode {
+    // Jump to the next page when the selection goes past the bottom, like rofi.
+    #[default]
+    Page,
+    // Scroll by a row, keeping scroll_margin rows around the selection.
+    Smooth,
+}
+
+// In the config file this is just a string: "focused", "pointer", "primary" or the
+// name of an output (what xrandr calls it, like "DP-1").
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Monitor {
+    // The monitor with the focused window on it (or the pointer, if nothing has focus).
+    #[default]
+    Focused,
+    // The monitor the mouse pointer is on.
+    Pointer,
+    // The one xrandr says is primary.
+    Primary,
+    Named(String),
+}
+
+impl std::str::FromStr for Monitor {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "focused" => Monitor::Focused,
+            "pointer" => Monitor::Pointer,
+            "primary" => Monitor::Primary,
+            name => Monitor::Named(name.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for Monitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Monitor::Focused => write!(f, "focused"),
+            Monitor::Pointer => write!(f, "pointer"),
+            Monitor::Primary => write!(f, "primary"),
+            Monitor::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Monitor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        // This can't fail, every string is a valid output name.
+        Ok(name.parse().unwrap())
+    }
+}
+
+impl Serialize for Monitor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+// An RGBA color that piston can use directly. In the config file, colors are
+// written as hex strings ("#rrggbb" or "#rrggbbaa").
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color(pub [f32; 4]);
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            font: Font::default(),
+            window: Window::default(),
+            lines: DEFAULT_LINES,
+            terminal: DEFAULT_TERMINAL.to_string(),
+            mode: Mode::default(),
+            prompt: None,
+            theme: DEFAULT_THEME.to_string(),
+            colors: Colors::default(),
+            palette: Palette::default(),
+            monitor: Monitor::default(),
+            vi_mode: ViMode::default(),
+            word_chars: String::new(),
+            history_size: DEFAULT_HISTORY_SIZE,
+            mouse_accept: MouseAccept::default(),
+            scroll_mode: ScrollMode::default(),
+            scroll_margin: DEFAULT_SCROLL_MARGIN,
+            wrap_around: false,
+            ellipsis: Ellipsis::default(),
+            keybindings: Keybindings::default(),
+        }
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Font {
+            family: DEFAULT_FONT_NAME.to_string(),
+            size: DEFAULT_FONT_SIZE,
+            prompt_size: DEFAULT_PROMPT_FONT_SIZE,
+        }
+    }
+}
+
+impl Font {
+    // Set the family, and the size too if the pattern has one. Only fontconfig's own
+    // ways of writing a size count, since plenty of families end in a number, like
+    // "Font Awesome 5". The size is taken out of the pattern, so `size` is the only
+    // place it's kept.
+    pub fn set_family(&mut self, family: &str) {
+        let family = family.trim();
+        let (name, properties) = match find_unescaped(family, ':') {
+            Some(colon) => (&family[..colon], Some(&family[colon + 1..])),
+            None => (family, None),
+        };
+
+        // "Family-12"
+        let mut pattern = match find_unescaped(name, '-') {
+            Some(dash) => match parse_font_size(&name[dash + 1..]) {
+                Some(size) if dash > 0 => {
+                    self.size = size;
+                    name[..dash].to_string()
+                }
+                _ => name.to_string(),
+            },
+            None => name.to_string(),
+        };
+
+        // "Family:size=12"
+        let mut rest = properties;
+        while let Some(properties) = rest {
+            let (property, next) = match find_unescaped(properties, ':') {
+                Some(colon) => (&properties[..colon], Some(&properties[colon + 1..])),
+                None => (properties, None),
+            };
+            match property.strip_prefix("size=").and_then(parse_font_size) {
+                Some(size) => self.size = size,
+                None => {
+                    pattern.push(':');
+                    pattern.push_str(property);
+                }
+            }
+            rest = next;
+        }
+        self.family = pattern;
+    }
+}
+
+// Where the first `wanted` in a fontconfig pattern is that isn't escaped with a
+// backslash.
+fn find_unescaped(pattern: &str, wanted: char) -> Option<usize> {
+    let mut escaped = false;
+    for (index, ch) in pattern.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else if ch == wanted {
+            return Some(index);
+        }
+    }
+    None
+}
+
+fn parse_font_size(size: &str) -> Option<u32> {
+    size.trim().parse::<u32>().ok().filter(|&size| size > 0)
+}
+
+impl Colors {
+    pub fn is_empty(&self) -> bool {
+        self.background.is_none() && self.foreground.is_none()
+    }
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Window {
+            width: DEFAULT_WINDOW_WIDTH,
+            height: DEFAULT_WINDOW_HEIGHT,
+            padding: DEFAULT_PADDING,
+            shrink: false,
+            scale: None,
+            anchor: Anchor::default(),
+            x_offset: 0,
+            y_offset: 0,
+        }
+    }
+}
+
+impl Color {
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if !(digits.len() == 6 || digits.len() == 8) || !digits.is_ascii() {
+            return None;
+        }
+
+        let mut color = [1.0; 4];
+        for (i, channel) in color.iter_mut().enumerate().take(digits.len() / 2) {
+            *channel = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).ok()? as f32 / 255.0;
+        }
+        Some(Color(color))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [r, g, b, a] = self.0.map(|channel| (channel * 255.0).round() as u8);
+        if a == 255 {
+            write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        }
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex = String::deserialize(deserializer)?;
+        Color::from_hex(&hex).ok_or_else(|| {
+            de::Error::custom(format!(
+                "invalid color \"{}\", expected \"#rrggbb\" or \"#rrggbbaa\"",
+                hex
+            ))
+        })
+    }
+}
+
+fn deserialize_scale<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let scale = f64::deserialize(deserializer)?;
+    if !(scale > 0.0 && scale.is_finite()) {
+        return Err(de::Error::custom("scale must be greater than zero"));
+    }
+    Ok(Some(scale))
+}
+
+// Zero lines, a zero-pixel window or a zero-pixel font are never what anyone meant, and
+// they make the drawing code divide by zero, so we reject them while parsing. Doing it
+// here (instead of after the fact) means toml tells the user where the bad value is.
+fn deserialize_padding<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let padding = f64::deserialize(deserializer)?;
+    if !(padding >= 0.0 && padding.is_finite()) {
+        return Err(de::Error::custom("padding must be zero or greater"));
+    }
+    Ok(padding)
+}
+
+fn deserialize_nonzero<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default + PartialEq,
+{
+    let value = T::deserialize(deserializer)?;
+    if value == T::default() {
+        return Err(de::Error::custom("value must be greater than zero"));
+    }
+    Ok(value)
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Json(PathBuf, serde_json::Error),
+    UnknownTheme(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            // toml's error messages already include the line and column.
+            ConfigError::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Json(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::UnknownTheme(name) => write!(
+                f,
+                "unknown theme \"{}\" (see --list-themes for the ones that exist)",
+                name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    // $XDG_CONFIG_HOME/wmcontroller/config.toml, or ~/.config/wmcontroller/config.toml
+    // if XDG_CONFIG_HOME isn't set.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("wmcontroller").join("config.toml"))
+    }
+
+    // Load the config from the default location. Not having a config file at all is fine
+    // (you just get the defaults), but a config file that doesn't parse is an error.
+    pub fn load() -> Result<Self, ConfigError> {
+        match Self::default_path() {
+            Some(path) => Self::load_if_exists(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn load_if_exists(path: &Path) -> Result<Self, ConfigError> {
+        if path.exists() {
+            Self::load_from(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        Self::parse(&contents).map_err(|e| ConfigError::Parse(path.to_owned(), e))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        let mut config: Config = toml::from_str(contents)?;
+        let family = config.font.family.clone();
+        config.font.set_family(&family);
+        Ok(config)
+    }
+
+    pub fn prompt(&self) -> &str {
+        self.prompt
+            .as_deref()
+            .unwrap_or_else(|| self.mode.default_prompt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_config_file_means_the_defaults() {
+        let path = std::env::temp_dir()
+            .join(format!("wmcontroller-config-{}", std::process::id()))
+            .join("config.toml");
+        assert_eq!(Config::load_if_exists(&path).unwrap(), Config::default());
+        // Asking for a file that isn't there with --config is still an error, though.
+        assert!(matches!(Config::load_from(&path), Err(ConfigError::Io(..))));
+    }
+
+    #[test]
+    fn keys_that_are_left_out_keep_their_defaults() {
+        let config = Config::parse("terminal = \"foot\"\n[font]\nsize = 12\n").unwrap();
+        assert_eq!(config.terminal, "foot");
+        assert_eq!(config.font.size, 12);
+        assert_eq!(config.font.family, DEFAULT_FONT_NAME);
+        assert_eq!(config.font.prompt_size, DEFAULT_PROMPT_FONT_SIZE);
+        assert_eq!(config.window, Window::default());
+        assert_eq!(config.lines, DEFAULT_LINES);
+    }
+
+    #[test]
+    fn misspelled_keys_are_rejected() {
+        let error = Config::parse("line = 7\n").unwrap_err();
+        assert!(error.to_string().contains("unknown field `line`"));
+        // In the tables too.
+        assert!(Config::parse("[window]\nwidht = 640\n").is_err());
+        assert!(Config::parse("[font]\nfamliy = \"monospace\"\n").is_err());
+    }
+
+    #[test]
+    fn font_sizes_in_patterns() {
+        let mut font = Font::default();
+        font.set_family("JetBrains Mono-12:bold");
+        assert_eq!(
+            (font.family.as_str(), font.size),
+            ("JetBrains Mono:bold", 12)
+        );
+        font.set_family("monospace:size=10:italic");
+        assert_eq!((font.family.as_str(), font.size), ("monospace:italic", 10));
+        // A number on the end is part of the family's name.
+        font.set_family("Font Awesome 5");
+        assert_eq!((font.family.as_str(), font.size), ("Font Awesome 5", 10));
+        font.set_family("Source Han Sans 2:bold");
+        assert_eq!(
+            (font.family.as_str(), font.size),
+            ("Source Han Sans 2:bold", 10)
+        );
+        // And so is an escaped dash.
+        font.set_family("Some\\-1");
+        assert_eq!((font.family.as_str(), font.size), ("Some\\-1", 10));
+    }
+
+    #[test]
+    fn negative_padding_is_rejected() {
+        for padding in ["-1.0", "nan", "inf"] {
+            let config = format!("[window]\npadding = {}\n", padding);
+            let error = Config::parse(&config).unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .contains("padding must be zero or greater"),
+                "{:?}",
+                padding
+            );
+        }
+        assert_eq!(
+            Config::parse("[window]\npadding = 0.0\n")
+                .unwrap()
+                .window
+                .padding,
+            0.0
+        );
+    }
+
+    #[test]
+    fn colors_from_hex() {
+        assert_eq!(
+            Color::from_hex("#ff0000"),
+            Some(Color([1.0, 0.0, 0.0, 1.0]))
+        );
+        assert_eq!(
+            Color::from_hex("#00ff0080"),
+            Some(Color([0.0, 1.0, 0.0, 128.0 / 255.0]))
+        );
+        // The # is optional.
+        assert_eq!(Color::from_hex("0000ff"), Some(Color([0.0, 0.0, 1.0, 1.0])));
+        for bad in ["", "#", "#fff", "#ff00000", "#gg0000", "#ff00é"] {
+            assert_eq!(Color::from_hex(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn zero_is_rejected() {
+        for config in [
+            "lines = 0\n",
+            "[font]\nsize = 0\n",
+            "[font]\nprompt_size = 0\n",
+            "[window]\nwidth = 0\n",
+            "[window]\nheight = 0\n",
+        ] {
+            let error = Config::parse(config).unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .contains("value must be greater than zero"),
+                "{:?}",
+                config
+            );
+        }
+    }
+}
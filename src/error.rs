@@ -0,0 +1,57 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Everything that can go wrong, for anything that would rather not crash over it.
+// Errors while starting up end up on stderr (see main), since there's no window to
+// show them in yet. Once the window is open, things like a program that won't start
+// are shown in the window instead, so you can pick something else.
+
+use crate::configuration::ConfigError;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    // The config file or the theme (see configuration.rs).
+    Config(ConfigError),
+    Fontconfig,
+    FontNotFound(String),
+    Window(String),
+    NoXConnection,
+    Replay(PathBuf, io::Error),
+    Record(PathBuf, io::Error),
+    // A .desktop entry whose Exec line we can't make sense of: its name and the line.
+    MalformedExec(String, String),
+    // The program that didn't start, and why.
+    Launch(String, io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Config(err) => write!(f, "Failed to load config: {}", err),
+            Error::Fontconfig => write!(f, "Failed to start fontconfig"),
+            Error::FontNotFound(family) => write!(f, "Failed to find font {:?}", family),
+            Error::Window(err) => write!(f, "Failed to open the window: {}", err),
+            Error::NoXConnection => write!(f, "Failed to talk to the X server"),
+            Error::Replay(path, err) => {
+                write!(f, "Failed to read the recording {:?}: {}", path, err)
+            }
+            Error::Record(path, err) => {
+                write!(f, "Failed to start recording to {:?}: {}", path, err)
+            }
+            Error::MalformedExec(name, exec) => {
+                write!(f, "{} has a malformed Exec line: {:?}", name, exec)
+            }
+            Error::Launch(program, err) => write!(f, "Failed to run {}: {}", program, err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ConfigError> for Error {
+    fn from(err: ConfigError) -> Self {
+        Error::Config(err)
+    }
+}
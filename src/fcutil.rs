@@ -0,0 +1,54 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Small helpers for finding fonts with fontconfig. The fontconfig crate covers looking
+// a font up by family, but not by a whole pattern or by what it can draw, so those go
+// through fontconfig-sys and live here with the rest of the unsafe.
+//
+// A pattern is what fc-match takes: a family with anything else after colons, like
+// "JetBrains Mono:bold:italic" or "monospace:weight=200".
+
+use fontconfig::{Fontconfig, Pattern, FC_CHARSET};
+use fontconfig_sys::fontconfig as sys;
+use std::ffi::CString;
+use std::path::PathBuf;
+
+// The file of the font fontconfig picks for `pattern`, like `fc-match pattern`.
+pub fn find(fc: &Fontconfig, pattern: &str) -> Option<PathBuf> {
+    parse(fc, pattern)?
+        .font_match()
+        .filename()
+        .map(PathBuf::from)
+}
+
+// The file of a font that can draw `ch`, as close to `pattern` as fontconfig can find,
+// like `fc-match "pattern:charset=<ch>"`. fontconfig always finds *something*, so
+// whoever loads it still has to check that it has the glyph.
+pub fn find_covering(fc: &Fontconfig, pattern: &str, ch: char) -> Option<PathBuf> {
+    let mut pattern = parse(fc, pattern)?;
+    unsafe {
+        let charset = sys::FcCharSetCreate();
+        if charset.is_null() {
+            return None;
+        }
+        sys::FcCharSetAddChar(charset, ch as u32);
+        // The pattern takes its own reference to the charset.
+        sys::FcPatternAddCharSet(pattern.pat, FC_CHARSET.as_ptr(), charset);
+        sys::FcCharSetDestroy(charset);
+    }
+    let found = pattern.font_match();
+    found.filename().map(PathBuf::from)
+}
+
+fn parse<'fc>(fc: &'fc Fontconfig, pattern: &str) -> Option<Pattern<'fc>> {
+    let name = CString::new(pattern).ok()?;
+    unsafe {
+        let parsed = sys::FcNameParse(name.as_ptr() as *const sys::FcChar8);
+        if parsed.is_null() {
+            return None;
+        }
+        // from_pattern takes a reference of its own, so we let go of ours.
+        let pattern = Pattern::from_pattern(fc, parsed);
+        sys::FcPatternDestroy(parsed);
+        Some(pattern)
+    }
+}
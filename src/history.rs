@@ -0,0 +1,171 @@
+/* SPDX-License-Identifier: Zlib */
+
+// The searches you picked something with, so Alt+P and Ctrl+R can bring them back
+// (see Search). Each mode gets its own file in $XDG_DATA_HOME/wmcontroller, since
+// what you type to find an application has nothing to do with what you type in
+// dmenu mode. The files are one search per line, oldest first.
+
+use crate::configuration::Mode;
+use log::warn;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub struct History {
+    path: Option<PathBuf>,
+    entries: Vec<String>,
+    // How many entries we keep. Zero turns history off.
+    size: usize,
+}
+
+impl History {
+    pub fn file_path(mode: Mode) -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| {
+            dir.join("wmcontroller")
+                .join(format!("{}-history", mode.name()))
+        })
+    }
+
+    // A missing or unreadable history file just means an empty history; it's not
+    // worth refusing to start over.
+    pub fn load(mode: Mode, size: usize) -> Self {
+        let path = if size > 0 {
+            History::file_path(mode)
+        } else {
+            None
+        };
+        let entries = match path {
+            Some(ref path) => read_entries(path),
+            None => Vec::new(),
+        };
+        let mut history = History {
+            path,
+            entries,
+            size,
+        };
+        history.trim();
+        history
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn limit(&self) -> usize {
+        self.size
+    }
+
+    // For when the config is reloaded. Turning the history on or off starts over the
+    // way load does; otherwise the entries we have are just trimmed to the new size.
+    pub fn set_limit(&mut self, mode: Mode, size: usize) {
+        if size == 0 || self.size == 0 {
+            *self = History::load(mode, size);
+        } else {
+            self.size = size;
+            self.trim();
+        }
+    }
+
+    // Add a search as the newest entry. Searching for the same thing again moves it
+    // to the end instead of adding it twice.
+    pub fn add(&mut self, entry: &str) {
+        if entry.is_empty() || self.size == 0 {
+            return;
+        }
+        self.entries.retain(|old_entry| old_entry != entry);
+        self.entries.push(entry.to_string());
+        self.trim();
+    }
+
+    pub fn save(&self) {
+        if let Some(ref path) = self.path {
+            if let Err(e) = write_entries(path, &self.entries) {
+                warn!("Failed to save the search history to {:?}: {}", path, e);
+            }
+        }
+    }
+
+    fn trim(&mut self) {
+        let excess = self.entries.len().saturating_sub(self.size);
+        self.entries.drain(..excess);
+    }
+}
+
+fn read_entries(path: &Path) -> Vec<String> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => contents
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            warn!("Failed to read the search history from {:?}: {}", path, e);
+            Vec::new()
+        }
+    }
+}
+
+// Written to a temporary file and renamed over the old one, so two launchers closing
+// at once can't leave a half-written history behind.
+fn write_entries(path: &Path, entries: &[String]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut contents = entries.join("\n");
+    contents.push('\n');
+    let temporary_path = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&temporary_path, contents)?;
+    std::fs::rename(&temporary_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newest_entries_are_kept_without_duplicates() {
+        let mut history = History {
+            path: None,
+            entries: Vec::new(),
+            size: 3,
+        };
+        for entry in ["firefox", "gimp", "", "firefox", "steam", "htop"] {
+            history.add(entry);
+        }
+        assert_eq!(history.entries(), ["firefox", "steam", "htop"]);
+    }
+
+    #[test]
+    fn shrinking_keeps_the_newest_entries() {
+        let mut history = History {
+            path: None,
+            entries: vec![
+                "firefox".to_string(),
+                "steam".to_string(),
+                "htop".to_string(),
+            ],
+            size: 3,
+        };
+        history.set_limit(Mode::Run, 2);
+        assert_eq!(history.entries(), ["steam", "htop"]);
+        history.set_limit(Mode::Run, 0);
+        assert!(history.entries().is_empty());
+        assert_eq!(history.path, None);
+    }
+
+    #[test]
+    fn history_survives_a_round_trip() {
+        let dir = std::env::temp_dir().join(format!("wmcontroller-history-{}", std::process::id()));
+        let path = dir.join("run-history");
+        let mut history = History {
+            path: Some(path.clone()),
+            entries: Vec::new(),
+            size: 10,
+        };
+        history.add("echo hello");
+        history.add("make");
+        history.save();
+        assert_eq!(read_entries(&path), ["echo hello", "make"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
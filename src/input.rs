@@ -0,0 +1,588 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Keyboard handling that every widget shares. This keeps track of the modifier keys
+// (so the widgets don't each have to) and turns key chords like Ctrl+P into named
+// actions like move-up. The widgets only ever see actions, so every binding can be
+// changed in the [keybindings] table of the config file:
+//
+//     [keybindings]
+//     move-up = ["up", "ctrl+p", "ctrl+k"]
+//     kill-line = ["ctrl+shift+k"]
+//     copy = []
+//
+// An action that's in the table loses its default bindings, so an empty list unbinds
+// it. The same chord can't be bound to two actions, and we check for that when the
+// config is loaded instead of picking one of them at random.
+
+use piston_window::{Button, ButtonArgs, ButtonState, Event, Input, Key};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    // The select list.
+    MoveUp,
+    MoveDown,
+    PageUp,
+    PageDown,
+    FirstEntry,
+    LastEntry,
+    Accept,
+    // Leave vi's insert mode, or close the window.
+    Cancel,
+    // Moving around the search bar.
+    CursorLeft,
+    CursorRight,
+    LineStart,
+    LineEnd,
+    WordLeft,
+    WordRight,
+    // Editing the search bar.
+    DeleteBackward,
+    DeleteForward,
+    DeleteWordBackward,
+    // Delete everything after the cursor, like Ctrl+K in Emacs.
+    KillLine,
+    // And everything before it, like Ctrl+U in readline.
+    KillToStart,
+    // The clipboard (see clipboard.rs).
+    PasteClipboard,
+    PastePrimary,
+    Copy,
+    // Going through earlier searches (see history.rs).
+    HistoryPrevious,
+    HistoryNext,
+    // Search the history as you type, like Ctrl+R in readline.
+    HistorySearch,
+}
+
+// What you get without a [keybindings] table. These are the same keys that were
+// hard-coded before keybindings were configurable.
+const DEFAULT_BINDINGS: &[(Action, &[&str])] = &[
+    (Action::MoveUp, &["up", "ctrl+p"]),
+    (Action::MoveDown, &["down", "ctrl+n"]),
+    (Action::PageUp, &["pageup"]),
+    (Action::PageDown, &["pagedown"]),
+    // Home and End already move the cursor in the search bar.
+    (Action::FirstEntry, &["ctrl+home"]),
+    (Action::LastEntry, &["ctrl+end"]),
+    (Action::Accept, &["return"]),
+    (Action::Cancel, &["escape"]),
+    (Action::CursorLeft, &["left"]),
+    (Action::CursorRight, &["right"]),
+    (Action::LineStart, &["home", "ctrl+a"]),
+    (Action::LineEnd, &["end", "ctrl+e"]),
+    (Action::WordLeft, &["alt+b"]),
+    (Action::WordRight, &["alt+f"]),
+    (Action::DeleteBackward, &["backspace"]),
+    (Action::DeleteForward, &["delete", "ctrl+d"]),
+    (Action::DeleteWordBackward, &["ctrl+backspace"]),
+    (Action::KillLine, &["ctrl+k"]),
+    (Action::KillToStart, &["ctrl+u"]),
+    (Action::PasteClipboard, &["ctrl+v"]),
+    (Action::PastePrimary, &["shift+insert"]),
+    (Action::Copy, &["ctrl+c"]),
+    (Action::HistoryPrevious, &["alt+p"]),
+    (Action::HistoryNext, &["alt+n"]),
+    (Action::HistorySearch, &["ctrl+r"]),
+];
+
+// Keys with names that aren't just the letter or digit on them. The first name for a
+// key is the one we print.
+const KEY_NAMES: &[(&str, Key)] = &[
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+    ("return", Key::Return),
+    ("enter", Key::Return),
+    ("escape", Key::Escape),
+    ("esc", Key::Escape),
+    ("backspace", Key::Backspace),
+    ("delete", Key::Delete),
+    ("insert", Key::Insert),
+    ("tab", Key::Tab),
+    ("space", Key::Space),
+    ("f1", Key::F1),
+    ("f2", Key::F2),
+    ("f3", Key::F3),
+    ("f4", Key::F4),
+    ("f5", Key::F5),
+    ("f6", Key::F6),
+    ("f7", Key::F7),
+    ("f8", Key::F8),
+    ("f9", Key::F9),
+    ("f10", Key::F10),
+    ("f11", Key::F11),
+    ("f12", Key::F12),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub super_key: bool,
+}
+
+impl Modifiers {
+    // Text typed while one of these is held is a shortcut, not something to type.
+    // Shift is fine, that's just capital letters.
+    pub fn block_text(&self) -> bool {
+        self.ctrl || self.alt || self.super_key
+    }
+}
+
+// The modifier keys that are held down. Both sides are kept track of on their own, so
+// letting go of one Alt while the other one is still down doesn't let go of Alt.
+#[derive(Debug, Clone, Copy, Default)]
+struct HeldModifiers {
+    l_ctrl: bool,
+    r_ctrl: bool,
+    l_alt: bool,
+    r_alt: bool,
+    l_shift: bool,
+    r_shift: bool,
+    l_super: bool,
+    r_super: bool,
+}
+
+impl HeldModifiers {
+    // Keep track of the modifier keys going up and down. Returns whether `ev` was one.
+    fn update(&mut self, ev: &Event) -> bool {
+        if let Event::Input(
+            Input::Button(ButtonArgs {
+                button: Button::Keyboard(key),
+                state,
+                ..
+            }),
+            _,
+        ) = ev
+        {
+            let held = match key {
+                Key::LCtrl => &mut self.l_ctrl,
+                Key::RCtrl => &mut self.r_ctrl,
+                Key::LAlt => &mut self.l_alt,
+                Key::RAlt => &mut self.r_alt,
+                Key::LShift => &mut self.l_shift,
+                Key::RShift => &mut self.r_shift,
+                Key::LGui => &mut self.l_super,
+                Key::RGui => &mut self.r_super,
+                _ => return false,
+            };
+            *held = *state == ButtonState::Press;
+            return true;
+        }
+        false
+    }
+
+    fn ctrl(&self) -> bool {
+        self.l_ctrl || self.r_ctrl
+    }
+
+    fn alt(&self) -> bool {
+        self.l_alt || self.r_alt
+    }
+
+    fn shift(&self) -> bool {
+        self.l_shift || self.r_shift
+    }
+
+    fn super_key(&self) -> bool {
+        self.l_super || self.r_super
+    }
+
+    // Which modifiers are held, whichever side they're on.
+    fn modifiers(&self) -> Modifiers {
+        Modifiers {
+            ctrl: self.ctrl(),
+            alt: self.alt(),
+            shift: self.shift(),
+            super_key: self.super_key(),
+        }
+    }
+}
+
+// Letters, digits and space. Every key with a name in KEY_NAMES other than space is
+// one you press to do something instead.
+fn types_text(key: Key) -> bool {
+    key == Key::Space || !KEY_NAMES.iter().any(|(_, named)| *named == key)
+}
+
+// A key plus the modifiers held with it, written like "ctrl+shift+k".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct KeyChord {
+    pub modifiers: Modifiers,
+    pub key: Key,
+}
+
+impl FromStr for KeyChord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.to_lowercase();
+        let mut parts: Vec<&str> = lowercase.split('+').map(str::trim).collect();
+        // "ctrl++" would be nice, but there's no + key on a US keyboard anyway.
+        let key_name = parts.pop().unwrap_or_default();
+
+        let mut modifiers = Modifiers::default();
+        for part in parts {
+            let modifier = match part {
+                "ctrl" | "control" => &mut modifiers.ctrl,
+                "alt" | "meta" => &mut modifiers.alt,
+                "shift" => &mut modifiers.shift,
+                "super" | "mod4" => &mut modifiers.super_key,
+                _ => return Err(format!("unknown modifier `{}` in `{}`", part, s)),
+            };
+            *modifier = true;
+        }
+
+        let key = if let Some((_, key)) = KEY_NAMES.iter().find(|(name, _)| *name == key_name) {
+            *key
+        } else {
+            // Letters and digits are the only other keys with an obvious name. Piston
+            // numbers those keys by their ASCII code.
+            let mut chars = key_name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) if ch.is_ascii_lowercase() || ch.is_ascii_digit() => {
+                    Key::from(ch as u32)
+                }
+                _ => return Err(format!("unknown key `{}` in `{}`", key_name, s)),
+            }
+        };
+        Ok(KeyChord { modifiers, key })
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (held, name) in [
+            (self.modifiers.ctrl, "ctrl"),
+            (self.modifiers.alt, "alt"),
+            (self.modifiers.shift, "shift"),
+            (self.modifiers.super_key, "super"),
+        ] {
+            if held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match KEY_NAMES.iter().find(|(_, key)| *key == self.key) {
+            Some((name, _)) => write!(f, "{}", name),
+            // Everything else came from a letter or digit (see from_str).
+            None => write!(f, "{}", (self.key as u32 as u8) as char),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyChord {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let chord = String::deserialize(deserializer)?;
+        chord.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for KeyChord {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+// The [keybindings] table, and which action every chord ends up with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keybindings {
+    // Only what the config file changed, so --dump-config doesn't print every default.
+    overrides: BTreeMap<Action, Vec<KeyChord>>,
+    actions: HashMap<KeyChord, Action>,
+}
+
+impl Keybindings {
+    pub fn new(overrides: BTreeMap<Action, Vec<KeyChord>>) -> Result<Self, String> {
+        let mut bindings: BTreeMap<Action, Vec<KeyChord>> = DEFAULT_BINDINGS
+            .iter()
+            .map(|(action, chords)| {
+                let chords = chords
+                    .iter()
+                    .map(|chord| chord.parse().expect("Default keybinding doesn't parse"))
+                    .collect();
+                (*action, chords)
+            })
+            .collect();
+        for (action, chords) in &overrides {
+            bindings.insert(*action, chords.clone());
+        }
+
+        let mut actions = HashMap::new();
+        for (action, chords) in &bindings {
+            for chord in chords {
+                if let Some(other_action) = actions.insert(*chord, *action) {
+                    if other_action != *action {
+                        return Err(format!(
+                            "`{}` is bound to both {} and {}",
+                            chord,
+                            action_name(other_action),
+                            action_name(*action)
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(Keybindings { overrides, actions })
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.overrides.is_empty()
+    }
+
+    pub fn action(&self, chord: &KeyChord) -> Option<Action> {
+        self.actions.get(chord).copied()
+    }
+}
+
+// The name of an action like it's written in the config file.
+fn action_name(action: Action) -> String {
+    toml::Value::try_from(action)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("{:?}", action))
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Keybindings::new(BTreeMap::new()).expect("The default keybindings conflict")
+    }
+}
+
+impl<'de> Deserialize<'de> for Keybindings {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let overrides = BTreeMap::<Action, Vec<KeyChord>>::deserialize(deserializer)?;
+        Keybindings::new(overrides).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Keybindings {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.overrides.serialize(serializer)
+    }
+}
+
+// Where an event should go after the keyboard had a look at it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    // It was a bound chord.
+    Action(Action),
+    // Anything else the widgets might care about, like typed text.
+    Widgets,
+    // Text typed as part of a shortcut, or a modifier key.
+    Nowhere,
+}
+
+// The modifier state plus the keybindings, which is all it takes to route an event.
+pub struct Keyboard {
+    modifiers: HeldModifiers,
+    keybindings: Keybindings,
+}
+
+impl Keyboard {
+    pub fn new(keybindings: Keybindings) -> Self {
+        Keyboard {
+            modifiers: HeldModifiers::default(),
+            keybindings,
+        }
+    }
+
+    pub fn set_keybindings(&mut self, keybindings: Keybindings) {
+        self.keybindings = keybindings;
+    }
+
+    pub fn route(&mut self, ev: &Event) -> Route {
+        if self.modifiers.update(ev) {
+            return Route::Nowhere;
+        }
+        match ev {
+            Event::Input(
+                Input::Button(ButtonArgs {
+                    button: Button::Keyboard(key),
+                    state: ButtonState::Press,
+                    ..
+                }),
+                _,
+            ) => {
+                let chord = KeyChord {
+                    modifiers: self.modifiers.modifiers(),
+                    key: *key,
+                };
+                // Shift doesn't change what keys like Backspace or Return do, and it's
+                // easy to still be holding it after typing a capital letter. So unless
+                // the chord with Shift is bound to something, it's the same as the key
+                // without it. Keys that type text keep their Shift, since Shift+A is
+                // an A and not an a.
+                let unshifted = || {
+                    if !chord.modifiers.shift || types_text(chord.key) {
+                        return None;
+                    }
+                    let modifiers = Modifiers {
+                        shift: false,
+                        ..chord.modifiers
+                    };
+                    self.keybindings.action(&KeyChord { modifiers, ..chord })
+                };
+                match self.keybindings.action(&chord).or_else(unshifted) {
+                    Some(action) => Route::Action(action),
+                    None => Route::Widgets,
+                }
+            }
+            Event::Input(Input::Text(_), _) if self.modifiers.modifiers().block_text() => {
+                Route::Nowhere
+            }
+            _ => Route::Widgets,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::key;
+
+    fn config(keybindings: &str) -> Result<Keybindings, toml::de::Error> {
+        toml::from_str(keybindings)
+    }
+
+    #[test]
+    fn chords_round_trip() {
+        for chord in [
+            "ctrl+p",
+            "shift+insert",
+            "ctrl+alt+shift+super+f12",
+            "7",
+            "return",
+        ] {
+            assert_eq!(chord.parse::<KeyChord>().unwrap().to_string(), chord);
+        }
+        assert_eq!(
+            "Control+Enter".parse::<KeyChord>().unwrap().to_string(),
+            "ctrl+return"
+        );
+        assert!("ctrl+".parse::<KeyChord>().is_err());
+        assert!("hyper+a".parse::<KeyChord>().is_err());
+        assert!("ctrl+pgup".parse::<KeyChord>().is_err());
+    }
+
+    #[test]
+    fn letting_go_of_one_side_keeps_the_other() {
+        let mut keyboard = Keyboard::new(Keybindings::default());
+        keyboard.route(&key(Key::LAlt, ButtonState::Press));
+        keyboard.route(&key(Key::RAlt, ButtonState::Press));
+        keyboard.route(&key(Key::LAlt, ButtonState::Release));
+        assert_eq!(
+            keyboard.route(&key(Key::P, ButtonState::Press)),
+            Route::Action(Action::HistoryPrevious)
+        );
+        keyboard.route(&key(Key::RAlt, ButtonState::Release));
+        assert_eq!(
+            keyboard.route(&key(Key::P, ButtonState::Press)),
+            Route::Widgets
+        );
+    }
+
+    #[test]
+    fn modifiers_pick_the_action() {
+        let mut keyboard = Keyboard::new(Keybindings::default());
+        assert_eq!(
+            keyboard.route(&key(Key::P, ButtonState::Press)),
+            Route::Widgets
+        );
+        keyboard.route(&key(Key::RCtrl, ButtonState::Press));
+        assert_eq!(
+            keyboard.route(&key(Key::P, ButtonState::Press)),
+            Route::Action(Action::MoveUp)
+        );
+        // Ctrl+P doesn't type a p.
+        assert_eq!(
+            keyboard.route(&Event::Input(Input::Text("p".to_string()), None)),
+            Route::Nowhere
+        );
+        keyboard.route(&key(Key::RCtrl, ButtonState::Release));
+        keyboard.route(&key(Key::LShift, ButtonState::Press));
+        assert_eq!(
+            keyboard.route(&key(Key::Insert, ButtonState::Press)),
+            Route::Action(Action::PastePrimary)
+        );
+        // But shift does type.
+        assert_eq!(
+            keyboard.route(&Event::Input(Input::Text("P".to_string()), None)),
+            Route::Widgets
+        );
+    }
+
+    #[test]
+    fn shift_is_ignored_on_keys_that_dont_type() {
+        let mut keyboard = Keyboard::new(Keybindings::default());
+        keyboard.route(&key(Key::LShift, ButtonState::Press));
+        assert_eq!(
+            keyboard.route(&key(Key::Backspace, ButtonState::Press)),
+            Route::Action(Action::DeleteBackward)
+        );
+        assert_eq!(
+            keyboard.route(&key(Key::Return, ButtonState::Press)),
+            Route::Action(Action::Accept)
+        );
+        // Shift+Insert has a binding of its own, which wins.
+        assert_eq!(
+            keyboard.route(&key(Key::Insert, ButtonState::Press)),
+            Route::Action(Action::PastePrimary)
+        );
+        // Ctrl+Shift+K isn't Ctrl+K.
+        keyboard.route(&key(Key::LCtrl, ButtonState::Press));
+        assert_eq!(
+            keyboard.route(&key(Key::K, ButtonState::Press)),
+            Route::Widgets
+        );
+        assert_eq!(
+            keyboard.route(&key(Key::Backspace, ButtonState::Press)),
+            Route::Action(Action::DeleteWordBackward)
+        );
+    }
+
+    #[test]
+    fn overrides_replace_the_defaults() {
+        let keybindings =
+            config("move-up = [\"ctrl+k\"]\nkill-line = [\"ctrl+shift+k\"]\n").unwrap();
+        let action = |chord: &str| keybindings.action(&chord.parse().unwrap());
+        assert_eq!(action("ctrl+k"), Some(Action::MoveUp));
+        assert_eq!(action("ctrl+shift+k"), Some(Action::KillLine));
+        // move-up lost its defaults.
+        assert_eq!(action("up"), None);
+        // Everything else keeps them.
+        assert_eq!(action("down"), Some(Action::MoveDown));
+    }
+
+    #[test]
+    fn conflicts_are_rejected() {
+        let error = config("move-up = [\"ctrl+k\"]\n").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("`ctrl+k` is bound to both move-up and kill-line"));
+        assert!(config("accept = [\"return\"]\ncancel = [\"return\"]\n").is_err());
+        assert!(config("not-an-action = [\"a\"]\n").is_err());
+    }
+}
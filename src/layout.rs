@@ -0,0 +1,270 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Where everything in the window goes. This used to be numbers sprinkled through the
+// draw functions (a 700x260 list at 40, 200 and so on). Now every widget says how big
+// it'd like to be (Widget::measure) and gets told where it goes (Widget::arrange), and
+// containers like ApplicationLauncher use the helpers in here to split their space up
+// between their children.
+//
+// From the top, the launcher has:
+//
+//     padding
+//     the prompt, prompt_size tall down to its baseline
+//     SPACING for the bits of the prompt that hang below the baseline
+//     SPACING
+//     the search bar, tall enough for the font
+//     SPACING
+//     the list, which gets whatever height is left
+//     padding
+//
+// With the defaults, everything is as high up and as tall as before it was configurable,
+// but the search bar and the list are 720 wide instead of 700, since they now go up to
+// the padding on the right like they do on the left.
+
+use crate::configuration::{Config, Ellipsis};
+use crate::render::{Font, PlacedChar};
+use std::collections::HashSet;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+// The space between the prompt, the search bar and the list.
+pub const SPACING: f64 = 20.0;
+
+// How far text is from the left edge of the search bar and of each row.
+pub const TEXT_INSET: f64 = 15.0;
+
+// What stands in for the part of some text that was cut out (see elide).
+pub const ELLIPSIS: &str = "…";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    // Top to bottom.
+    Vertical,
+    // Left to right.
+    Horizontal,
+}
+
+// Put children with the given sizes one after another in `rect`, with `spacing`
+// between them. Vertically, every child gets the full width of `rect` and the height
+// it asked for; horizontally, it's the other way around. A size can be f64::INFINITY
+// to mean "whatever is left over", which only makes sense for the last child.
+pub fn stack(rect: [f64; 4], direction: Direction, sizes: &[f64], spacing: f64) -> Vec<[f64; 4]> {
+    let [x, y, width, height] = rect;
+    let (start, length) = match direction {
+        Direction::Vertical => (y, height),
+        Direction::Horizontal => (x, width),
+    };
+    let end = start + length;
+
+    let mut position = start;
+    sizes
+        .iter()
+        .map(|&size| {
+            let size = size.min(end - position).max(0.0);
+            let child = match direction {
+                Direction::Vertical => [x, position, width, size],
+                Direction::Horizontal => [position, y, size, height],
+            };
+            position = (position + size + spacing).min(end);
+            child
+        })
+        .collect()
+}
+
+// `rect` with `padding` taken off of every side.
+pub fn inset(rect: [f64; 4], padding: f64) -> [f64; 4] {
+    [
+        rect[0] + padding,
+        rect[1] + padding,
+        (rect[2] - 2.0 * padding).max(0.0),
+        (rect[3] - 2.0 * padding).max(0.0),
+    ]
+}
+
+// Whether `point` is inside `rect`.
+pub fn contains(rect: [f64; 4], point: [f64; 2]) -> bool {
+    point[0] >= rect[0]
+        && point[1] >= rect[1]
+        && point[0] < rect[0] + rect[2]
+        && point[1] < rect[1] + rect[3]
+}
+
+// The part of `a` that's also in `b`, which is empty if they don't overlap.
+pub fn intersect(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+    let left = a[0].max(b[0]);
+    let top = a[1].max(b[1]);
+    let right = (a[0] + a[2]).min(b[0] + b[2]);
+    let bottom = (a[1] + a[3]).min(b[1] + b[3]);
+    [left, top, (right - left).max(0.0), (bottom - top).max(0.0)]
+}
+
+// Which parts of `text` to show so it fits in `width` with an ELLIPSIS between them:
+// the start of it, and the end of it. If it fits already (or `ellipsis` is None), the
+// start is all of it and there's no end, and nothing needs to go between them. Text is
+// only ever cut between graphemes, and the spaces next to a cut go too, so it doesn't
+// come out as "foo …".
+pub fn elide(
+    font: &Font,
+    size: u32,
+    text: &str,
+    width: f64,
+    ellipsis: Ellipsis,
+) -> (Range<usize>, Range<usize>) {
+    let (placed, text_width) = font.layout(size, text);
+    let all = (0..text.len(), text.len()..text.len());
+    if ellipsis == Ellipsis::None || text_width <= width {
+        return all;
+    }
+
+    // Where text can be cut, and how wide everything in front of that is. That's in the
+    // order it's in the text, which isn't the order it's drawn in if some of it is
+    // right-to-left, so it's added up from the widths of the chars in front.
+    let graphemes: HashSet<usize> = text.grapheme_indices(true).map(|(i, _)| i).collect();
+    let mut logical: Vec<&PlacedChar> = placed.iter().collect();
+    logical.sort_by_key(|place| place.index);
+    let mut logical = logical.into_iter().peekable();
+    let mut in_front = 0.0;
+    let mut cuts = Vec::new();
+    for (i, _) in text.char_indices() {
+        if graphemes.contains(&i) {
+            cuts.push((i, in_front));
+        }
+        while let Some(place) = logical.next_if(|place| place.index == i) {
+            in_front += place.width;
+        }
+    }
+    cuts.push((text.len(), text_width));
+    // The last cut that leaves no more than `room` in front of it.
+    let head_end = |room: f64| {
+        cuts.iter()
+            .rev()
+            .find(|&&(_, x)| x <= room)
+            .map_or((0, 0.0), |&cut| cut)
+    };
+
+    let room = (width - font.text_width(size, ELLIPSIS)).max(0.0);
+    let (head, tail_start) = match ellipsis {
+        Ellipsis::End => (head_end(room).0, text.len()),
+        Ellipsis::Middle => {
+            let (head, head_width) = head_end(room / 2.0);
+            // The first cut that leaves no more than what's left behind it.
+            let tail_start = cuts
+                .iter()
+                .find(|&&(i, x)| i >= head && text_width - x <= room - head_width)
+                .map_or(text.len(), |&(i, _)| i);
+            (head, tail_start)
+        }
+        Ellipsis::None => return all,
+    };
+    let head = text[..head].trim_end().len();
+    let tail_start = text.len() - text[tail_start..].trim_start().len();
+    (0..head, tail_start..text.len())
+}
+
+// The search bar is a bit over twice as tall as its text, which is 40 pixels with the
+// default font size.
+pub fn search_height(config: &Config) -> f64 {
+    f64::from(config.font.size) * 2.0 + 4.0
+}
+
+// The prompt and what hangs below its baseline.
+pub fn prompt_height(config: &Config) -> f64 {
+    f64::from(config.font.prompt_size) + SPACING
+}
+
+// How much of the window the launcher uses above the list.
+pub fn list_top(config: &Config) -> f64 {
+    config.window.padding + prompt_height(config) + SPACING + search_height(config) + SPACING
+}
+
+// The rows split up what's left of the window when it's at its full height, so
+// they're the same height whether the window shrinks to fit them or not.
+pub fn row_height(config: &Config) -> f64 {
+    let list_height = f64::from(config.window.height) - list_top(config) - config.window.padding;
+    list_height.max(0.0) / config.lines as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_keep_the_old_heights_but_fill_the_width() {
+        let config = Config::default();
+        let content = inset([0.0, 0.0, 800.0, 500.0], config.window.padding);
+        let children = stack(
+            content,
+            Direction::Vertical,
+            &[
+                prompt_height(&config),
+                search_height(&config),
+                f64::INFINITY,
+            ],
+            SPACING,
+        );
+        // The prompt's baseline was at 100. The search bar and the list were 700 wide.
+        assert_eq!(children[0], [40.0, 40.0, 720.0, 80.0]);
+        assert_eq!(children[1], [40.0, 140.0, 720.0, 40.0]);
+        assert_eq!(children[2], [40.0, 200.0, 720.0, 260.0]);
+        assert_eq!(list_top(&config), 200.0);
+        assert_eq!(row_height(&config), 260.0 / 7.0);
+    }
+
+    #[test]
+    fn eliding() {
+        let font = crate::snapshot::test_font();
+        let text = "a name that is much too long";
+        let width = font.text_width(18, "a name that") + font.text_width(18, ELLIPSIS);
+        let shown = |ellipsis| {
+            let (head, tail) = elide(&font, 18, text, width, ellipsis);
+            (&text[head], &text[tail])
+        };
+        assert_eq!(shown(Ellipsis::End), ("a name that", ""));
+        assert_eq!(shown(Ellipsis::Middle), ("a nam", "o long"));
+        assert_eq!(shown(Ellipsis::None), (text, ""));
+        // Text that fits is left alone.
+        assert_eq!(
+            elide(&font, 18, "short", width, Ellipsis::End),
+            (0..5, 5..5)
+        );
+        // A combining accent stays with its letter.
+        let (head, _) = elide(&font, 18, "ééééé", font.text_width(18, "é…"), Ellipsis::End);
+        assert_eq!(&"ééééé"[head], "é");
+    }
+
+    #[test]
+    fn huge_fonts_dont_overflow() {
+        let mut config = Config::default();
+        config.font.size = u32::MAX;
+        assert_eq!(search_height(&config), f64::from(u32::MAX) * 2.0 + 4.0);
+    }
+
+    #[test]
+    fn intersecting() {
+        let list = [40.0, 200.0, 720.0, 260.0];
+        assert_eq!(
+            intersect(list, [0.0, 0.0, 800.0, 300.0]),
+            [40.0, 200.0, 720.0, 100.0]
+        );
+        // Rectangles that don't touch leave nothing.
+        assert_eq!(intersect(list, [0.0, 0.0, 10.0, 10.0])[2..], [0.0, 0.0]);
+    }
+
+    #[test]
+    fn stacking_sideways_and_running_out_of_room() {
+        let rows = stack(
+            [10.0, 10.0, 100.0, 30.0],
+            Direction::Horizontal,
+            &[50.0, 40.0, 40.0],
+            5.0,
+        );
+        assert_eq!(
+            rows,
+            [
+                [10.0, 10.0, 50.0, 30.0],
+                [65.0, 10.0, 40.0, 30.0],
+                [110.0, 10.0, 0.0, 30.0],
+            ]
+        );
+    }
+}
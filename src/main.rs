@@ -1,7 +1,6 @@
 /* SPDX-License-Identifier: Zlib */
 
 use core::time::Duration;
-use glutin::dpi::{PhysicalPosition, Position};
 use glutin_window::GlutinWindow;
 use piston_window::*;
 use winit::{
@@ -10,39 +9,145 @@ use winit::{
     window::WindowBuilder,
 };
 
+use clap::Parser;
 use fontconfig::Fontconfig;
 
-use log::debug;
+use log::{debug, error, info, warn};
+use std::path::PathBuf;
 
 mod application_launcher;
+mod cli;
+mod clipboard;
 mod configuration;
+mod error;
+mod fcutil;
+mod history;
+mod input;
+mod layout;
+mod palette;
+mod placement;
+mod recording;
+mod reload;
+mod render;
+#[cfg(test)]
+mod snapshot;
+mod theme;
 mod widgets;
+mod xutil;
 
-use configuration::{BACKGROUND_COLOR, FONT_NAME};
-use widgets::{search, select, Widget};
+use clipboard::ClipboardRequest;
+use configuration::{Config, ConfigError, Mode};
+use error::Error;
+use recording::{Happening, Header, Player, Recorder, Recording};
+use render::{
+    piston::{GlyphCaches, PistonRenderer},
+    Font, Renderer,
+};
+use theme::Theme;
+use widgets::{search, select, select::EntryState, Widget};
 
 fn main() {
-    const WIDTH: u32 = 800;
-    const HEIGHT: u32 = 500;
     flexi_logger::Logger::try_with_env()
         .unwrap()
         .start()
         .unwrap();
 
+    let cli = cli::Cli::parse();
+
+    // Anything that goes wrong before the window is open ends up here. Once it's open,
+    // the launcher shows what goes wrong itself.
+    if let Err(e) = run(&cli) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: &cli::Cli) -> Result<(), Error> {
+    if cli.serve_clipboard {
+        clipboard::serve();
+        return Ok(());
+    }
+
+    if cli.list_themes {
+        for name in Theme::list() {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    // We'd rather not open at all than open with something the user didn't ask for.
+    let mut config = load_config(cli)?;
+    debug!("Config is {:?}", config);
+
+    if cli.dump_config {
+        print!(
+            "{}",
+            toml::to_string(&config).expect("Failed to serialize the configuration")
+        );
+        return Ok(());
+    }
+
+    // We build the entries before we open the window. In dmenu mode this reads all of
+    // stdin, and we don't want a frozen window on screen while we wait for it.
+    let mut application_launcher =
+        application_launcher::ApplicationLauncher::new(&config, cli.filter.as_deref());
+    application_launcher.set_entry_state(&cli.urgent, EntryState::Urgent);
+    application_launcher.set_entry_state(&cli.active, EntryState::Active);
+
+    // Find font for window
+    let fc = Fontconfig::new().ok_or(Error::Fontconfig)?;
+    let mut font = load_font(&fc, &config.font.family)?;
+
+    // Both of these say which entries they go with, which we only know now.
+    let mut player = match cli.replay {
+        Some(ref path) => {
+            let recording = Recording::load(path).map_err(|e| Error::Replay(path.clone(), e))?;
+            if recording.header != Header::new(application_launcher.entry_names()) {
+                warn!(
+                    "The entries aren't the ones {:?} was recorded with, so it might not play back the same",
+                    path
+                );
+            }
+            Some(Player::new(recording))
+        }
+        None => None,
+    };
+    let mut recorder = match cli.record {
+        Some(ref path) => Some(
+            Recorder::create(path, &Header::new(application_launcher.entry_names()))
+                .map_err(|e| Error::Record(path.clone(), e))?,
+        ),
+        None => None,
+    };
+
+    let width = config.window.width;
+    let height = config.window.height;
+
+    // winit works out how much to scale the window by from Xft.dpi or the monitor's
+    // DPI, unless we tell it otherwise. Everything we draw is sized in its logical
+    // pixels, so that scales everything.
+    if let Some(scale) = config.window.scale {
+        application_launcher::override_scale_factor(scale);
+    }
+
     let eventloop = glutin::event_loop::EventLoop::with_user_event();
     let window_builder = WindowBuilder::new()
         // This is the magic setting that lets the window float like how you see in rofi
         .with_override_redirect(true)
-        .with_inner_size(LogicalSize::new(WIDTH, HEIGHT));
+        .with_inner_size(LogicalSize::new(width, height));
 
-    // The WIDTH, HEIGHT here doesn't matter, so we set it above with with_inner_size.
-    let window_settings = WindowSettings::new("WMController", [WIDTH, HEIGHT])
+    // The width, height here doesn't matter, so we set it above with with_inner_size.
+    let window_settings = WindowSettings::new("WMController", [width, height])
         .decorated(false)
-        .exit_on_esc(true)
+        .exit_on_esc(false)
         .resizable(false);
 
-    let gw: GlutinWindow =
-        GlutinWindow::from_raw(&window_settings, eventloop, window_builder).unwrap();
+    let gw: GlutinWindow = GlutinWindow::from_raw(&window_settings, eventloop, window_builder)
+        .map_err(|e| Error::Window(e.to_string()))?;
+
+    // The xresources theme reads from the X server, so we can only load the theme
+    // once we have a connection to it.
+    let mut theme = load_theme(&config, gw.ctx.window())?;
 
     // Center the window and grab keys
 
@@ -53,11 +158,17 @@ fn main() {
         // something useful with the window (eg. text input and whatnot)
         // Idea stolen from https://github.com/seanpringle/simpleswitcher/blob/master/simpleswitcher.c
         let window_ref = gw.ctx.window();
+        // Without X, we couldn't have made the window, but winit doesn't know that.
+        let (xconn, display, xwindow) = window_ref
+            .xlib_xconnection()
+            .zip(window_ref.xlib_display())
+            .zip(window_ref.xlib_window())
+            .map(|((xconn, display), xwindow)| (xconn, display, xwindow))
+            .ok_or(Error::NoXConnection)?;
         unsafe {
-            let xconn = window_ref.xlib_xconnection().unwrap();
-            while ((*xconn).xlib.XGrabKeyboard)(
-                window_ref.xlib_display().unwrap() as *mut x11::ffi::_XDisplay,
-                window_ref.xlib_window().unwrap(),
+            while (xconn.xlib.XGrabKeyboard)(
+                display as *mut x11::ffi::_XDisplay,
+                xwindow,
                 x11::ffi::True,
                 x11::ffi::GrabModeAsync,
                 x11::ffi::GrabModeAsync,
@@ -70,20 +181,14 @@ fn main() {
             }
         }
 
-        if let Some(monitor) = window_ref.current_monitor() {
-            let screen_size = monitor.size();
-            let window_size = window_ref.inner_size();
-            debug!("Size of screen is {:?}", screen_size);
-            debug!("Size of window is {:?}", window_size);
-
-            window_ref.set_outer_position(Position::Physical(PhysicalPosition {
-                // The window will be centered on the wrong monitor
-                // if we don't move x and y by the monitor's position
-                // as an offset.
-                x: monitor.position().x + (screen_size.width / 2 - window_size.width / 2) as i32,
-                y: monitor.position().y + (screen_size.height / 2 - window_size.height / 2) as i32,
-            }));
+        // We grab the pointer too, so that clicking somewhere else closes the window
+        // like it does in rofi. Unlike the keyboard, this is nice to have, so we only
+        // try once.
+        if !xutil::grab_pointer(&xconn, xwindow) {
+            warn!("Failed to grab the pointer, clicking outside the window won't close it");
         }
+
+        placement::place_window(window_ref, &config.monitor, &config.window);
     }
 
     // Stolen from https://github.com/PistonDevelopers/piston_window/blob/master/src/lib.rs
@@ -92,37 +197,262 @@ fn main() {
         .unwrap_or(Api::opengl(3, 2));
     let samples = window_settings.get_samples();
 
-    let opengl = OpenGL::from_api(api).unwrap();
+    let opengl = OpenGL::from_api(api.clone())
+        .ok_or_else(|| Error::Window(format!("{:?} isn't a version of OpenGL", api)))?;
     let mut window = PistonWindow::new(opengl, samples, gw);
 
-    // Find font for window—panic is fine here
-    // TODO let users specify their own font (or me if I want to change it)
-    let fc = Fontconfig::new().unwrap();
-    let font = fc.find(FONT_NAME, None).expect("Failed to find font!");
-    let mut glyph_cache = window
-        // TODO We want to end up using some kind of font loader here
-        // so we can specify the font family/name and it finds the tttf
-        .load_font(font.path)
-        .unwrap();
+    let mut glyph_caches = GlyphCaches::new(&mut window);
 
-    let mut application_launcher = application_launcher::ApplicationLauncher::new();
+    // Not being able to watch the config for changes isn't worth refusing to start over.
+    let mut config_watcher = match reload::ConfigWatcher::new(watched_files(cli, &config)) {
+        Ok(config_watcher) => Some(config_watcher),
+        Err(e) => {
+            warn!("Failed to watch the config for changes: {}", e);
+            None
+        }
+    };
 
     // let mut events = Events::new(EventSettings::new().lazy(true));
 
+    // Where the mouse is, so we can tell when a click is outside the window. Piston's
+    // button events don't say where they happened.
+    let mut cursor_position = [0.0, 0.0];
+
     while let Some(ev) = window.next() {
         // Event things go here
         // We use press_args to store the key being pressed to pass it to the
         // search bar
 
-        application_launcher.handle_event(&ev);
-        if let Some(_args) = ev.render_args() {
-            window.draw_2d(&ev, |c, g, device| {
-                // TODO we want to make all these colors configurable,
-                // or at least global.
-                clear(BACKGROUND_COLOR, g);
-                application_launcher.draw([40.0, 100.0], &c, g, &mut glyph_cache);
-                glyph_cache.factory.encoder.flush(device);
-            });
+        // Apply changes to the config and theme files. Everything is re-read and
+        // validated first, and if anything is wrong, we keep using what we had.
+        if let Some(ref mut config_watcher) = config_watcher {
+            if config_watcher.changed() {
+                let reloaded = load_config(cli).and_then(|new_config| {
+                    let new_theme = load_theme(&new_config, window.window.ctx.window())?;
+                    Ok((new_config, new_theme))
+                });
+                match reloaded {
+                    Ok((mut new_config, new_theme)) => {
+                        info!("Reloaded the configuration");
+                        if new_config.mode != config.mode {
+                            // The entries are only read at startup.
+                            warn!("Changing the mode needs a restart");
+                            new_config.mode = config.mode;
+                        }
+                        if new_config.window.scale != config.window.scale {
+                            warn!("Changing the scale needs a restart");
+                            new_config.window.scale = config.window.scale;
+                        }
+                        if new_config.font.family != config.font.family {
+                            match load_font(&fc, &new_config.font.family) {
+                                Ok(new_font) => {
+                                    glyph_caches = GlyphCaches::new(&mut window);
+                                    font = new_font;
+                                }
+                                Err(e) => {
+                                    error!("{}", e);
+                                    new_config.font.family = config.font.family.clone();
+                                }
+                            }
+                        }
+                        if new_config.window != config.window
+                            || new_config.monitor != config.monitor
+                        {
+                            let window_ref = window.window.ctx.window();
+                            window_ref.set_inner_size(LogicalSize::new(
+                                new_config.window.width,
+                                new_config.window.height,
+                            ));
+                            placement::place_window(
+                                window_ref,
+                                &new_config.monitor,
+                                &new_config.window,
+                            );
+                        }
+                        application_launcher.reconfigure(&new_config);
+                        config_watcher.set_files(watched_files(cli, &new_config));
+                        config = new_config;
+                        theme = new_theme;
+                    }
+                    Err(e) => error!("Keeping the old configuration: {}", e),
+                }
+            }
         }
+
+        // While replaying, the recording takes the place of the real keyboard and
+        // mouse until it runs out.
+        let events = match player {
+            Some(ref mut player) if !player.finished() => {
+                let mut events = player.due_events();
+                if !is_keyboard_or_mouse(&ev) {
+                    events.push(ev);
+                }
+                events
+            }
+            _ => vec![ev],
+        };
+
+        for ev in events {
+            // Everything gets laid out again before every event, so the widgets always
+            // know where they are on screen for the mouse (see layout.rs).
+            let size = window.size();
+            application_launcher.arrange([0.0, 0.0, size.width, size.height], &config, &font);
+
+            if let (Some(ref mut recorder), Event::Input(ref input, _)) = (&mut recorder, &ev) {
+                recorder.record(Happening::Input(input.clone()));
+            }
+
+            if let Some(position) = ev.mouse_cursor_args() {
+                cursor_position = position;
+            }
+            if let Some(Button::Mouse(_)) = ev.press_args() {
+                if !layout::contains(application_launcher.bounds(), cursor_position) {
+                    debug!("Clicked outside the window");
+                    window.set_should_close(true);
+                }
+            }
+
+            application_launcher.handle_event(&ev);
+            // We handle Escape ourselves instead of with exit_on_esc, since in vi mode it
+            // only closes the window from normal mode, and it can be rebound.
+            if application_launcher.wants_to_close() {
+                window.set_should_close(true);
+            }
+            match application_launcher.take_clipboard_request() {
+                Some(ClipboardRequest::Paste(selection)) => {
+                    // What was in the clipboard when it was recorded is in the recording.
+                    let text = match player {
+                        Some(ref mut player) => player.next_paste(),
+                        None => window
+                            .window
+                            .ctx
+                            .window()
+                            .xlib_xconnection()
+                            .and_then(|xconn| clipboard::paste(&xconn, selection)),
+                    };
+                    if let Some(text) = text {
+                        if let Some(ref mut recorder) = recorder {
+                            recorder.record(Happening::Paste(text.clone()));
+                        }
+                        application_launcher.paste(&text);
+                    }
+                }
+                Some(ClipboardRequest::Copy(text)) => clipboard::copy(&text),
+                None => {}
+            }
+            // With shrink set, the window is only as tall as the entries that match. The top
+            // of the window stays put, so the search bar doesn't move around as you type.
+            if config.window.shrink {
+                let height = shrunk_height(&application_launcher, &config, &font, size.width);
+                if (height - size.height).abs() >= 1.0 {
+                    window
+                        .window
+                        .ctx
+                        .window()
+                        .set_inner_size(LogicalSize::new(size.width, height));
+                }
+            }
+            if let Some(_args) = ev.render_args() {
+                window.draw_2d(&ev, |c, g, device| {
+                    let window_size = c.get_view_size();
+                    let mut renderer = PistonRenderer::new(c, g, &mut glyph_caches, &font);
+                    draw_window(
+                        &application_launcher,
+                        &config,
+                        &theme,
+                        &mut renderer,
+                        window_size,
+                    );
+                    glyph_caches.flush(device);
+                });
+            }
+        }
+    }
+
+    // Like dmenu, exit with an error if the user closed the window without picking
+    // anything, so scripts can tell the difference.
+    if config.mode == Mode::Dmenu {
+        std::process::exit(1);
     }
+    Ok(())
+}
+
+// What --replay stands in for. Everything else, like the window being resized or
+// closed, still comes from the window.
+fn is_keyboard_or_mouse(ev: &Event) -> bool {
+    matches!(
+        ev,
+        Event::Input(Input::Button(_) | Input::Move(_) | Input::Text(_), _)
+    )
+}
+
+// Built-in defaults, then the config file, then the command line.
+fn load_config(cli: &cli::Cli) -> Result<Config, ConfigError> {
+    let mut config = match cli.config {
+        Some(ref path) => Config::load_from(path)?,
+        None => Config::load()?,
+    };
+    // The command line wins over the config file.
+    cli.apply(&mut config);
+    Ok(config)
+}
+
+fn load_theme(config: &Config, window_ref: &winit::window::Window) -> Result<Theme, ConfigError> {
+    Theme::from_config(config, || {
+        window_ref
+            .xlib_xconnection()
+            .and_then(|xconn| xutil::resource_manager(&xconn))
+    })
+}
+
+// The font for `family`, which asks fontconfig for more fonts when it runs into
+// something `family` can't draw (see render/mod.rs).
+fn load_font(fc: &Fontconfig, family: &str) -> Result<Font, Error> {
+    let not_found = || Error::FontNotFound(family.to_string());
+    let path = fcutil::find(fc, family).ok_or_else(not_found)?;
+    let font = Font::load(&path).map_err(|_| not_found())?;
+    // The Font keeps this around, so it gets its own handle.
+    let fallback_fc = Fontconfig::new().ok_or(Error::Fontconfig)?;
+    let family = family.to_string();
+    Ok(font.with_fallback(move |ch| fcutil::find_covering(&fallback_fc, &family, ch)))
+}
+
+// Everything in the window, whatever it's being drawn with.
+fn draw_window<R: Renderer>(
+    application_launcher: &application_launcher::ApplicationLauncher,
+    config: &Config,
+    theme: &Theme,
+    renderer: &mut R,
+    window_size: [f64; 2],
+) {
+    renderer.clear(theme.window.background.0);
+    // With rounded corners, the corners outside of the border keep
+    // the background color, since the window itself isn't transparent.
+    renderer.rectangle(
+        [0.0, 0.0, window_size[0], window_size[1]],
+        [0.0; 4],
+        Some((theme.window.border_color.0, theme.window.border_width)),
+        theme.window.border_radius,
+    );
+    application_launcher.draw(config, theme, renderer);
+}
+
+// How tall the window is when window.shrink has it fit the entries that match.
+fn shrunk_height(
+    application_launcher: &application_launcher::ApplicationLauncher,
+    config: &Config,
+    font: &Font,
+    width: f64,
+) -> f64 {
+    application_launcher.measure([width, f64::from(config.window.height)], config, font)[1]
+}
+
+// The files that, when they change, make us reload.
+fn watched_files(cli: &cli::Cli, config: &Config) -> Vec<PathBuf> {
+    cli.config
+        .clone()
+        .or_else(Config::default_path)
+        .into_iter()
+        .chain(Theme::file_path(&config.theme))
+        .collect()
 }
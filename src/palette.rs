@@ -0,0 +1,228 @@
+/* SPDX-License-Identifier: Zlib */
+
+// A palette is the 16 terminal colors plus a background and foreground, which is what
+// Xresources and pywal give us. The "xresources" and "pywal" themes take a palette and
+// spread it over every slot of a theme (see Theme::from_palette), so WMController
+// matches the rest of your desktop.
+//
+// Colors that the palette source doesn't have come from [palette] in the config file,
+// and after that from the default theme's colors.
+
+use crate::configuration::{Color, ConfigError};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// The names a palette has colors for.
+pub const PALETTE_NAMES: &[&str] = &[
+    "background",
+    "foreground",
+    "color0",
+    "color1",
+    "color2",
+    "color3",
+    "color4",
+    "color5",
+    "color6",
+    "color7",
+    "color8",
+    "color9",
+    "color10",
+    "color11",
+    "color12",
+    "color13",
+    "color14",
+    "color15",
+];
+
+// Used for any color that neither the source nor the config has. These are the
+// default theme's colors and xterm's 16 colors.
+const DEFAULT_PALETTE: &[(&str, &str)] = &[
+    ("background", "#124551"),
+    ("foreground", "#ffffff"),
+    ("color0", "#000000"),
+    ("color1", "#cd0000"),
+    ("color2", "#00cd00"),
+    ("color3", "#cdcd00"),
+    ("color4", "#0000ee"),
+    ("color5", "#cd00cd"),
+    ("color6", "#00cdcd"),
+    ("color7", "#e5e5e5"),
+    ("color8", "#7f7f7f"),
+    ("color9", "#ff0000"),
+    ("color10", "#00ff00"),
+    ("color11", "#ffff00"),
+    ("color12", "#5c5cff"),
+    ("color13", "#ff00ff"),
+    ("color14", "#00ffff"),
+    ("color15", "#ffffff"),
+];
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct Palette(BTreeMap<String, Color>);
+
+impl Palette {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Only asks for names in PALETTE_NAMES, which always have a default.
+    pub fn get(&self, name: &str) -> Color {
+        self.0.get(name).copied().unwrap_or_else(|| {
+            let (_, hex) = DEFAULT_PALETTE
+                .iter()
+                .find(|(default_name, _)| *default_name == name)
+                .expect("Asked the palette for a color it doesn't have");
+            Color::from_hex(hex).unwrap()
+        })
+    }
+
+    // Fill in whatever this palette is missing from `fallbacks`.
+    pub fn or(mut self, fallbacks: &Palette) -> Self {
+        for (name, color) in &fallbacks.0 {
+            self.0.entry(name.clone()).or_insert(*color);
+        }
+        self
+    }
+
+    // Pick the palette out of an Xresources database (the RESOURCE_MANAGER property).
+    // For each color, the most specific resource wins: `wmcontroller.color4` over
+    // `wmcontroller*color4` over `*.color4` over `*color4`.
+    pub fn from_xresources(resources: &str) -> Self {
+        let mut resource_values: BTreeMap<&str, &str> = BTreeMap::new();
+        for line in resources.lines() {
+            if let Some((name, value)) = line.split_once(':') {
+                resource_values.insert(name.trim(), value.trim());
+            }
+        }
+
+        let mut palette = BTreeMap::new();
+        for name in PALETTE_NAMES {
+            let color = [
+                format!("wmcontroller.{}", name),
+                format!("wmcontroller*{}", name),
+                format!("*.{}", name),
+                format!("*{}", name),
+            ]
+            .iter()
+            .filter_map(|resource| resource_values.get(resource.as_str()))
+            .find_map(|value| parse_x_color(value));
+            if let Some(color) = color {
+                palette.insert(name.to_string(), color);
+            }
+        }
+        Palette(palette)
+    }
+
+    // Where pywal puts the colors it generated.
+    pub fn pywal_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("wal").join("colors.json"))
+    }
+
+    // Read pywal's colors.json, which looks like
+    //
+    //     {
+    //         "special": { "background": "#...", "foreground": "#...", "cursor": "#..." },
+    //         "colors": { "color0": "#...", ..., "color15": "#..." }
+    //     }
+    pub fn from_pywal(path: &Path) -> Result<Self, ConfigError> {
+        #[derive(Deserialize)]
+        struct PywalColors {
+            #[serde(default)]
+            special: BTreeMap<String, String>,
+            #[serde(default)]
+            colors: BTreeMap<String, String>,
+        }
+
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        let pywal: PywalColors =
+            serde_json::from_str(&contents).map_err(|e| ConfigError::Json(path.to_owned(), e))?;
+
+        let palette = pywal
+            .special
+            .iter()
+            .chain(pywal.colors.iter())
+            .filter(|(name, _)| PALETTE_NAMES.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.clone(), Color::from_hex(value)?)))
+            .collect();
+        Ok(Palette(palette))
+    }
+}
+
+// X colors are usually "#rrggbb", but `rgb:rr/gg/bb` (with 1 to 4 hex digits per
+// channel) shows up in Xresources too.
+fn parse_x_color(value: &str) -> Option<Color> {
+    if let Some(channels) = value.strip_prefix("rgb:") {
+        let mut color = [1.0; 4];
+        let mut channel_count = 0;
+        for (channel, digits) in color.iter_mut().zip(channels.split('/')) {
+            if digits.is_empty() || digits.len() > 4 {
+                return None;
+            }
+            let max = (1u32 << (4 * digits.len())) - 1;
+            *channel = u32::from_str_radix(digits, 16).ok()? as f32 / max as f32;
+            channel_count += 1;
+        }
+        return if channel_count == 3 {
+            Some(Color(color))
+        } else {
+            None
+        };
+    }
+    Color::from_hex(value)
+}
+
+impl<'de> Deserialize<'de> for Palette {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let colors = BTreeMap::<String, Color>::deserialize(deserializer)?;
+        if let Some(name) = colors
+            .keys()
+            .find(|name| !PALETTE_NAMES.contains(&name.as_str()))
+        {
+            return Err(de::Error::custom(format!(
+                "unknown palette color `{}`, expected background, foreground or color0 to color15",
+                name
+            )));
+        }
+        Ok(Palette(colors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xresources_most_specific_resource_wins() {
+        let palette = Palette::from_xresources(
+            "*color1:\t#111111\n*.color1:\t#222222\nwmcontroller.color1:\t#333333\n\
+             *.background:\trgb:00/80/ff\nXft.dpi:\t96\n",
+        );
+        assert_eq!(palette.get("color1"), Color::from_hex("#333333").unwrap());
+        assert_eq!(
+            palette.get("background"),
+            Color::from_hex("#0080ff").unwrap()
+        );
+        // Not in the resources, so it's the default.
+        assert_eq!(palette.get("color2"), Color::from_hex("#00cd00").unwrap());
+    }
+
+    #[test]
+    fn fallbacks_only_fill_in_missing_colors() {
+        let palette = Palette::from_xresources("*.color1: #111111\n");
+        let fallbacks: Palette =
+            toml::from_str("color1 = \"#222222\"\ncolor2 = \"#333333\"\n").unwrap();
+        let palette = palette.or(&fallbacks);
+        assert_eq!(palette.get("color1"), Color::from_hex("#111111").unwrap());
+        assert_eq!(palette.get("color2"), Color::from_hex("#333333").unwrap());
+    }
+
+    #[test]
+    fn unknown_palette_names_are_rejected() {
+        assert!(toml::from_str::<Palette>("color16 = \"#000000\"\n").is_err());
+    }
+}
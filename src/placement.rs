@@ -0,0 +1,158 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Where the window goes on the screen. With more than one monitor, winit's idea of the
+// monitor we're on is whichever one the window happened to be created on, so we work
+// it out ourselves from what the config asks for (see Monitor), and then put the
+// window against the part of that monitor it asks for (see Anchor).
+//
+// Everything here is in the X server's pixels, with (0, 0) at the top left of the
+// whole screen, which is what winit's monitor positions and sizes are in too.
+
+use crate::configuration::{self, Anchor, Monitor};
+use crate::xutil;
+use glutin::dpi::{PhysicalPosition, Position};
+use log::{debug, warn};
+use winit::monitor::MonitorHandle;
+use winit::platform::unix::WindowExtUnix;
+use winit::window::Window;
+
+// Put the window where the config says.
+pub fn place_window(window_ref: &Window, monitor: &Monitor, settings: &configuration::Window) {
+    let monitor = match find_monitor(window_ref, monitor).or_else(|| window_ref.current_monitor()) {
+        Some(monitor) => monitor,
+        None => {
+            warn!("Failed to find a monitor to put the window on");
+            return;
+        }
+    };
+
+    let window_size = window_ref.inner_size();
+    debug!("Putting the window on {:?}", monitor.name());
+    debug!("Size of screen is {:?}", monitor.size());
+    debug!("Size of window is {:?}", window_size);
+
+    // The offsets are in our pixels, like the window's size in the config.
+    let scale = window_ref.scale_factor();
+    let [x, y] = anchored(
+        area(&monitor),
+        [window_size.width as i32, window_size.height as i32],
+        settings.anchor,
+        [
+            (f64::from(settings.x_offset) * scale).round() as i32,
+            (f64::from(settings.y_offset) * scale).round() as i32,
+        ],
+    );
+    window_ref.set_outer_position(Position::Physical(PhysicalPosition { x, y }));
+}
+
+fn find_monitor(window_ref: &Window, monitor: &Monitor) -> Option<MonitorHandle> {
+    let monitors: Vec<MonitorHandle> = window_ref.available_monitors().collect();
+    let areas: Vec<[i32; 4]> = monitors.iter().map(area).collect();
+    let xconn = window_ref.xlib_xconnection();
+    let under_pointer = || {
+        let pointer = xutil::pointer_position(xconn.as_ref()?)?;
+        monitor_at(&areas, pointer)
+    };
+
+    let index = match monitor {
+        // A window that's on more than one monitor counts as being on the one with its
+        // middle.
+        Monitor::Focused => xconn
+            .as_ref()
+            .and_then(|xconn| xutil::active_window_geometry(xconn))
+            .and_then(|[x, y, width, height]| monitor_at(&areas, [x + width / 2, y + height / 2]))
+            .or_else(|| {
+                debug!("Nothing has focus, going by the pointer instead");
+                under_pointer()
+            }),
+        Monitor::Pointer => under_pointer(),
+        Monitor::Primary => {
+            let primary = window_ref.primary_monitor()?;
+            monitors.iter().position(|monitor| *monitor == primary)
+        }
+        Monitor::Named(name) => {
+            let index = monitors
+                .iter()
+                .position(|monitor| monitor.name().as_ref() == Some(name));
+            if index.is_none() {
+                warn!("There is no monitor named {:?}", name);
+            }
+            index
+        }
+    };
+    index.map(|index| monitors[index].clone())
+}
+
+// x, y, width and height of a monitor.
+fn area(monitor: &MonitorHandle) -> [i32; 4] {
+    let position = monitor.position();
+    let size = monitor.size();
+    [
+        position.x,
+        position.y,
+        size.width as i32,
+        size.height as i32,
+    ]
+}
+
+// Which of `areas` has `point` in it.
+fn monitor_at(areas: &[[i32; 4]], point: [i32; 2]) -> Option<usize> {
+    areas.iter().position(|&[x, y, width, height]| {
+        (x..x + width).contains(&point[0]) && (y..y + height).contains(&point[1])
+    })
+}
+
+// Where the top left of a window `size` big goes to be against `anchor` of `area`,
+// moved by `offset`.
+fn anchored(area: [i32; 4], size: [i32; 2], anchor: Anchor, offset: [i32; 2]) -> [i32; 2] {
+    // How far across the room the window has to move it goes: 0 is the left (or the
+    // top), 1 the middle and 2 the right (or the bottom), in halves.
+    let (across, down) = match anchor {
+        Anchor::Center => (1, 1),
+        Anchor::Top => (1, 0),
+        Anchor::Bottom => (1, 2),
+        Anchor::Left => (0, 1),
+        Anchor::Right => (2, 1),
+        Anchor::TopLeft => (0, 0),
+        Anchor::TopRight => (2, 0),
+        Anchor::BottomLeft => (0, 2),
+        Anchor::BottomRight => (2, 2),
+    };
+    [
+        area[0] + (area[2] - size[0]) * across / 2 + offset[0],
+        area[1] + (area[3] - size[1]) * down / 2 + offset[1],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 1920x1080 monitor with a 2560x1440 one to the right of it.
+    const AREAS: [[i32; 4]; 2] = [[0, 0, 1920, 1080], [1920, 0, 2560, 1440]];
+
+    #[test]
+    fn finding_the_monitor_a_point_is_on() {
+        assert_eq!(monitor_at(&AREAS, [100, 100]), Some(0));
+        assert_eq!(monitor_at(&AREAS, [1920, 100]), Some(1));
+        // Under the first monitor, which is shorter than the second one.
+        assert_eq!(monitor_at(&AREAS, [100, 1200]), None);
+    }
+
+    #[test]
+    fn anchoring() {
+        let size = [800, 500];
+        let on_second = |anchor, offset| anchored(AREAS[1], size, anchor, offset);
+        // Centering is what we've always done.
+        assert_eq!(on_second(Anchor::Center, [0, 0]), [1920 + 880, 470]);
+        assert_eq!(on_second(Anchor::Top, [0, 0]), [1920 + 880, 0]);
+        assert_eq!(on_second(Anchor::BottomRight, [0, 0]), [1920 + 1760, 940]);
+        // Offsets go right and down, so getting away from the bottom right corner
+        // takes negative ones.
+        assert_eq!(on_second(Anchor::TopLeft, [20, 30]), [1940, 30]);
+        assert_eq!(
+            on_second(Anchor::BottomRight, [-20, -30]),
+            [1920 + 1740, 910]
+        );
+    }
+}
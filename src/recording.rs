@@ -0,0 +1,271 @@
+/* SPDX-License-Identifier: Zlib */
+
+// --record and --replay. When something goes wrong, --record writes down everything
+// piston tells us about the keyboard and mouse (and what got pasted, since that comes
+// from the X server and not from an event), and --replay plays it back into the window
+// in place of the real keyboard and mouse, at the same pace.
+//
+// The log is one JSON object per line. The first line says which entries there were,
+// since the same keys do something else with other entries:
+//
+//     {"entries_hash":"5d1a3c4e8f0b2a17","entry_count":4}
+//     {"time":0.512,"input":{"Text":"f"}}
+//     {"time":1.03,"paste":"some text"}
+//
+// `time` is in seconds from when recording started. Every line is written out right
+// away, so the log has everything up to a crash.
+
+use log::warn;
+use piston_window::{Event, Input};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::Instant;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub entries_hash: String,
+    pub entry_count: usize,
+}
+
+impl Header {
+    pub fn new<'a>(names: impl Iterator<Item = &'a str>) -> Self {
+        // FNV-1a, which unlike std's hasher is the same everywhere, forever. The
+        // newline keeps ["ab", "c"] and ["a", "bc"] apart.
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut entry_count = 0;
+        for name in names {
+            for byte in name.bytes().chain(std::iter::once(b'\n')) {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+            entry_count += 1;
+        }
+        Header {
+            entries_hash: format!("{:016x}", hash),
+            entry_count,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Happening {
+    Input(Input),
+    Paste(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub time: f64,
+    #[serde(flatten)]
+    pub happening: Happening,
+}
+
+pub struct Recorder {
+    file: File,
+    start: Instant,
+    // So a full disk doesn't fill the log with the same warning.
+    failed: bool,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, header: &Header) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        writeln!(file, "{}", serde_json::to_string(header)?)?;
+        Ok(Recorder {
+            file,
+            start: Instant::now(),
+            failed: false,
+        })
+    }
+
+    pub fn record(&mut self, happening: Happening) {
+        let record = Record {
+            time: self.start.elapsed().as_secs_f64(),
+            happening,
+        };
+        let result = serde_json::to_string(&record)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(self.file, "{}", line));
+        if let Err(e) = result {
+            if !self.failed {
+                warn!("Failed to write to the recording: {}", e);
+                self.failed = true;
+            }
+        }
+    }
+}
+
+pub struct Recording {
+    pub header: Header,
+    pub records: Vec<Record>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let invalid = |number: usize, e: serde_json::Error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", number, e),
+            )
+        };
+
+        let header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?).map_err(|e| invalid(1, e))?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the recording is empty",
+                ))
+            }
+        };
+        let mut records = Vec::new();
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            records.push(serde_json::from_str(&line).map_err(|e| invalid(index + 2, e))?);
+        }
+        Ok(Recording { header, records })
+    }
+
+    // All of the input, as events, with no waiting in between.
+    #[cfg(test)]
+    pub fn events(&self) -> Vec<Event> {
+        self.records
+            .iter()
+            .filter_map(|record| match record.happening {
+                Happening::Input(ref input) => Some(Event::Input(input.clone(), None)),
+                Happening::Paste(_) => None,
+            })
+            .collect()
+    }
+}
+
+// Plays a recording back in real time.
+pub struct Player {
+    inputs: VecDeque<(f64, Input)>,
+    pastes: VecDeque<String>,
+    start: Instant,
+}
+
+impl Player {
+    pub fn new(recording: Recording) -> Self {
+        let mut inputs = VecDeque::new();
+        let mut pastes = VecDeque::new();
+        for record in recording.records {
+            match record.happening {
+                Happening::Input(input) => inputs.push_back((record.time, input)),
+                Happening::Paste(text) => pastes.push_back(text),
+            }
+        }
+        Player {
+            inputs,
+            pastes,
+            start: Instant::now(),
+        }
+    }
+
+    // The events that should have happened by now, and haven't been played yet.
+    pub fn due_events(&mut self) -> Vec<Event> {
+        let now = self.start.elapsed().as_secs_f64();
+        let mut events = Vec::new();
+        while let Some(&(time, _)) = self.inputs.front() {
+            if time > now {
+                break;
+            }
+            let (_, input) = self.inputs.pop_front().unwrap();
+            events.push(Event::Input(input, None));
+        }
+        events
+    }
+
+    // Pastes happen in the same order as they did when recording, so whenever
+    // something is pasted, it's the next one.
+    pub fn next_paste(&mut self) -> Option<String> {
+        self.pastes.pop_front()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piston_window::{Button, ButtonArgs, ButtonState, Key};
+
+    #[test]
+    fn entries_hash() {
+        let header = Header::new(["ab", "c"].iter().copied());
+        assert_eq!(header.entry_count, 2);
+        assert_eq!(header, Header::new(vec!["ab", "c"].into_iter()));
+        assert_ne!(header, Header::new(["a", "bc"].iter().copied()));
+    }
+
+    #[test]
+    fn recording_and_loading() {
+        let path =
+            std::env::temp_dir().join(format!("wmcontroller-test-{}.jsonl", std::process::id()));
+        let header = Header::new(["firefox", "foot"].iter().copied());
+        let press = Input::Button(ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Keyboard(Key::Down),
+            scancode: None,
+        });
+
+        let mut recorder = Recorder::create(&path, &header).unwrap();
+        recorder.record(Happening::Input(Input::Text("f".to_string())));
+        recorder.record(Happening::Paste("oot".to_string()));
+        recorder.record(Happening::Input(press.clone()));
+        drop(recorder);
+
+        let recording = Recording::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(recording.header, header);
+        assert_eq!(
+            recording.events(),
+            [
+                Event::Input(Input::Text("f".to_string()), None),
+                Event::Input(press, None)
+            ]
+        );
+        assert_eq!(
+            recording.records[1].happening,
+            Happening::Paste("oot".to_string())
+        );
+    }
+
+    #[test]
+    fn playing_back_in_time() {
+        let record = |time, text: &str| Record {
+            time,
+            happening: Happening::Input(Input::Text(text.to_string())),
+        };
+        let mut player = Player::new(Recording {
+            header: Header::new(std::iter::empty()),
+            records: vec![
+                record(0.0, "now"),
+                Record {
+                    time: 0.0,
+                    happening: Happening::Paste("pasted".to_string()),
+                },
+                record(3600.0, "later"),
+            ],
+        });
+        assert_eq!(
+            player.due_events(),
+            [Event::Input(Input::Text("now".to_string()), None)]
+        );
+        assert!(player.due_events().is_empty());
+        assert!(!player.finished());
+        assert_eq!(player.next_paste().as_deref(), Some("pasted"));
+        assert_eq!(player.next_paste(), None);
+    }
+}
@@ -0,0 +1,169 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Watches the config file and the theme file so changes show up in the running window
+// without having to restart it.
+//
+// We watch the directories the files are in instead of the files themselves. Most
+// editors save by writing a new file and renaming it over the old one, and a watch on
+// the old file would just stop working after the first save. It also means we notice
+// a config file that didn't exist when we started.
+//
+// If the directory isn't there either (no ~/.config/wmcontroller yet), we watch the
+// closest one above it that is, and move the watch down once the directory shows up.
+
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+
+pub struct ConfigWatcher {
+    inotify: Inotify,
+    // The directory each watch is for, since events only tell us the file name.
+    watched_dirs: HashMap<WatchDescriptor, PathBuf>,
+    // The files we actually care about. Everything else in those directories is ignored.
+    files: Vec<PathBuf>,
+    buffer: [u8; 4096],
+}
+
+impl ConfigWatcher {
+    pub fn new(files: Vec<PathBuf>) -> io::Result<Self> {
+        let mut watcher = ConfigWatcher {
+            inotify: Inotify::init()?,
+            watched_dirs: HashMap::new(),
+            files: Vec::new(),
+            buffer: [0; 4096],
+        };
+        watcher.set_files(files);
+        Ok(watcher)
+    }
+
+    // Change the files we're watching, e.g. because the config switched to another theme.
+    pub fn set_files(&mut self, files: Vec<PathBuf>) {
+        for dir in files.iter().filter_map(|file| file.parent()) {
+            // The directory not existing (yet) is normal, since none of these files
+            // have to exist. Until it does, we watch for it to be made.
+            for dir in dir.ancestors() {
+                if self.watched_dirs.values().any(|watched| watched == dir) {
+                    break;
+                }
+                let mask = WatchMask::CLOSE_WRITE
+                    | WatchMask::MOVED_TO
+                    | WatchMask::CREATE
+                    | WatchMask::DELETE;
+                match self.inotify.add_watch(dir, mask) {
+                    Ok(wd) => {
+                        debug!("Watching {:?} for changes", dir);
+                        self.watched_dirs.insert(wd, dir.to_owned());
+                        break;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        warn!("Failed to watch {:?} for changes: {}", dir, e);
+                        break;
+                    }
+                }
+            }
+        }
+        self.files = files;
+    }
+
+    // Check (without blocking) whether any of the files changed since the last call.
+    pub fn changed(&mut self) -> bool {
+        let events = match self.inotify.read_events(&mut self.buffer) {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Failed to read file change events: {}", e);
+                return false;
+            }
+        };
+
+        let mut changed = false;
+        let mut new_dirs = false;
+        // An editor saving a file can cause a handful of events at once, so we go
+        // through all of them and only report one change.
+        for event in events {
+            if let (Some(dir), Some(name)) = (self.watched_dirs.get(&event.wd), event.name) {
+                let path = dir.join(name);
+                if self.files.iter().any(|file| file == &path) {
+                    debug!("{:?} changed", path);
+                    changed = true;
+                } else if self
+                    .files
+                    .iter()
+                    .filter_map(|file| file.parent())
+                    .any(|parent| parent.starts_with(&path))
+                {
+                    new_dirs = true;
+                }
+            }
+        }
+
+        // A directory on the way to one of our files showed up, so we can watch closer
+        // to it now. The file could have been put in there before the watch was, which
+        // we'd never hear about, so if it's there now that counts as a change.
+        if new_dirs {
+            self.set_files(self.files.clone());
+            if self.files.iter().any(|file| file.exists()) {
+                debug!("One of the files we watch showed up along with its directory");
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_watched_files_count_as_changes() {
+        let dir = std::env::temp_dir().join(format!("wmcontroller-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.toml");
+
+        let mut watcher = ConfigWatcher::new(vec![config_path.clone()]).unwrap();
+        assert!(!watcher.changed());
+
+        std::fs::write(dir.join("something-else.toml"), "").unwrap();
+        assert!(!watcher.changed());
+
+        // Written the way editors do it: a new file renamed over the old one.
+        std::fs::write(dir.join("config.toml.tmp"), "lines = 3\n").unwrap();
+        std::fs::rename(dir.join("config.toml.tmp"), &config_path).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn directories_that_show_up_later() {
+        let dir =
+            std::env::temp_dir().join(format!("wmcontroller-reload-later-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Neither of these exist yet.
+        let config_dir = dir.join("config").join("wmcontroller");
+        let config_path = config_dir.join("config.toml");
+
+        let mut watcher = ConfigWatcher::new(vec![config_path.clone()]).unwrap();
+        std::fs::create_dir_all(&config_dir).unwrap();
+        // Nothing to load yet.
+        assert!(!watcher.changed());
+
+        std::fs::write(&config_path, "lines = 3\n").unwrap();
+        assert!(watcher.changed());
+
+        // And all at once, before we had the chance to watch the new directory.
+        let theme_dir = dir.join("themes");
+        let theme_path = theme_dir.join("mine.toml");
+        watcher.set_files(vec![config_path, theme_path.clone()]);
+        std::fs::create_dir_all(&theme_dir).unwrap();
+        std::fs::write(&theme_path, "").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,345 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Everything the widgets draw goes through Renderer, so they don't need to know what
+// actually puts the pixels somewhere. The window draws with PistonRenderer (OpenGL,
+// through piston), and the snapshot tests draw into memory with SoftwareRenderer
+// (tiny-skia), which doesn't need a GPU, or even an X server.
+//
+// Text is laid out by Font, which both of them share, so a widget that measures some
+// text gets the same answer no matter which one ends up drawing it.
+//
+// A Font is really a list of fonts ("faces"): the one from the config, and then any
+// that were found for chars it doesn't have, like CJK in an app's name, or symbols.
+// Every char is drawn with the first face that has it, on the same baseline.
+//
+// Right-to-left text is put in the order it's drawn in (and Arabic is joined up) by
+// shaping.rs before it's laid out, so what's laid out isn't always what's in the text.
+// Everything that's placed remembers where in the text it's from, though.
+
+pub mod piston;
+mod shaping;
+#[cfg(feature = "software-render")]
+pub mod software;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+pub trait Renderer {
+    // The font text is drawn with, so widgets can measure what they're about to draw.
+    fn font(&self) -> &Font;
+    // Fill everything with `color`, clip or not.
+    fn clear(&mut self, color: [f32; 4]);
+    // Draw a rectangle (x, y, width, height) with the given background and, if `border`
+    // is set, a border of (color, width). The border is centered on the edge, the way
+    // piston has always drawn them, so it goes `width` in and `width` out. A non-zero
+    // `radius` rounds the corners.
+    fn rectangle(
+        &mut self,
+        rect: [f64; 4],
+        background: [f32; 4],
+        border: Option<([f32; 4], f64)>,
+        radius: f64,
+    );
+    // Draw a line `width` thick from one point to the other.
+    fn line(&mut self, from: [f64; 2], to: [f64; 2], color: [f32; 4], width: f64);
+    // Draw `text` with its baseline starting at `position`, with the glyphs where
+    // Font::layout says they go.
+    fn text(&mut self, text: &str, color: [f32; 4], size: u32, position: [f64; 2]) {
+        let (placed, _) = self.font().layout(size, text);
+        self.placed_text(&placed, color, size, position);
+    }
+    // Draw some of what Font::layout placed, for drawing parts of text in different
+    // colors without moving anything.
+    fn placed_text(
+        &mut self,
+        placed: &[PlacedChar],
+        color: [f32; 4],
+        size: u32,
+        position: [f64; 2],
+    );
+    // Draw `rgba`, stretched to fill `rect`. The window doesn't have icons yet, so only
+    // the snapshot tests draw images for now.
+    #[allow(dead_code)]
+    fn image(&mut self, rect: [f64; 4], rgba: &Image);
+    // Until the matching pop_clip, only draw inside `rect` (and inside whatever clip
+    // there already was).
+    fn push_clip(&mut self, rect: [f64; 4]);
+    fn pop_clip(&mut self);
+}
+
+// A picture to draw with Renderer::image: 8-bit RGBA, not premultiplied, row by row
+// from the top left.
+#[allow(dead_code)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+// Where a char of some text goes, and which face draws it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacedChar {
+    pub ch: char,
+    // Where it's from in the text, as a byte index.
+    pub index: usize,
+    // An index into the faces of the Font (see Font::face).
+    pub face: usize,
+    pub x: f64,
+    // How far it is to whatever's placed after it.
+    pub width: f64,
+    pub rtl: bool,
+}
+
+// Clones share their faces, so a face that was found for one of them is there for all
+// of them.
+#[derive(Clone)]
+pub struct Font(Rc<RefCell<Faces>>);
+
+struct Faces {
+    // The font that was asked for comes first.
+    fonts: Vec<rusttype::Font<'static>>,
+    // Where each of `fonts` came from, so we don't load the same file twice.
+    paths: Vec<PathBuf>,
+    // Finds a font file that can draw a char, if we're allowed to look.
+    find: Option<Box<dyn Fn(char) -> Option<PathBuf>>>,
+    // Which face draws each char we've needed so far. We only look for a char once,
+    // since asking fontconfig isn't quick, and most text is the same few chars.
+    chosen: HashMap<char, usize>,
+}
+
+impl Font {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let font = load_face(path)?;
+        Ok(Font(Rc::new(RefCell::new(Faces {
+            fonts: vec![font],
+            paths: vec![path.to_path_buf()],
+            find: None,
+            chosen: HashMap::new(),
+        }))))
+    }
+
+    // When the faces we have don't have a char, ask `find` for a font file that does.
+    pub fn with_fallback<F>(self, find: F) -> Self
+    where
+        F: Fn(char) -> Option<PathBuf> + 'static,
+    {
+        {
+            let mut faces = self.0.borrow_mut();
+            faces.find = Some(Box::new(find));
+            // Anything we gave up on before might be found now.
+            faces.chosen.clear();
+        }
+        self
+    }
+
+    // One of the faces, for whatever draws with its own copy of it (see piston.rs).
+    pub fn face(&self, index: usize) -> rusttype::Font<'static> {
+        self.0.borrow().fonts[index].clone()
+    }
+
+    // The first face that has `ch`, going looking for one if none of them do. If
+    // nothing has it, it's the first face, which draws its replacement glyph.
+    pub fn face_for(&self, ch: char) -> usize {
+        let mut faces = self.0.borrow_mut();
+        if let Some(&index) = faces.chosen.get(&ch) {
+            return index;
+        }
+        let has = |font: &rusttype::Font| font.glyph(ch).id() != rusttype::GlyphId(0);
+        let index = match faces.fonts.iter().position(has) {
+            Some(index) => index,
+            None => faces
+                .find_face(ch)
+                .filter(|&index| has(&faces.fonts[index]))
+                .unwrap_or(0),
+        };
+        faces.chosen.insert(ch, index);
+        index
+    }
+    // The scale text of `size` points is drawn at, in pixels at 96 DPI (1.333 pixels a
+    // point). Everything uses it, so nothing moves depending on who draws it. More DPI
+    // than that is the renderer's business.
+    pub fn scale(size: u32) -> rusttype::Scale {
+        rusttype::Scale::uniform(size as f32 * 1.333)
+    }
+
+    // The glyph for `ch` from the face that draws it. Like piston's glyph cache, we
+    // draw U+FFFD for anything no face has (unless the first face doesn't have that
+    // either).
+    pub fn glyph(&self, ch: char) -> rusttype::Glyph<'static> {
+        let face = self.face(self.face_for(ch));
+        let glyph = face.glyph(ch);
+        if glyph.id() == rusttype::GlyphId(0) {
+            let replacement = face.glyph('\u{FFFD}');
+            if replacement.id() != rusttype::GlyphId(0) {
+                return replacement;
+            }
+        }
+        glyph
+    }
+
+    // Where each char of `text` goes, from left to right, and where the text ends.
+    // Piston's own Text doesn't kern, but we do, so we always lay text out here, and
+    // something placed text_width(..) along is right where the next glyph would be.
+    // Kerning only happens between two chars from the same face, since it's the face
+    // that knows how its glyphs fit together.
+    pub fn layout(&self, size: u32, text: &str) -> (Vec<PlacedChar>, f64) {
+        let scale = Self::scale(size);
+        let mut x = 0.0;
+        let mut placed: Vec<PlacedChar> = Vec::new();
+        for shaped in shaping::shape(text) {
+            let ch = shaped.ch;
+            let face = self.face_for(ch);
+            let font = self.face(face);
+            if let Some(previous) = placed.last_mut() {
+                if previous.face == face {
+                    let kerning = f64::from(font.pair_kerning(scale, previous.ch, ch));
+                    previous.width += kerning;
+                    x += kerning;
+                }
+            }
+            let width = f64::from(self.glyph(ch).scaled(scale).h_metrics().advance_width);
+            placed.push(PlacedChar {
+                ch,
+                index: shaped.index,
+                face,
+                x,
+                width,
+                rtl: shaped.rtl,
+            });
+            x += width;
+        }
+        (placed, x)
+    }
+
+    // How far along the text a cursor at `index` (a byte index) goes, given how it was
+    // laid out. It goes where the char after it starts, which is on that char's right
+    // if it's right-to-left. At the end of the text it goes where the last char ends
+    // instead.
+    pub fn caret_x(placed: &[PlacedChar], index: usize) -> f64 {
+        let after = placed
+            .iter()
+            .filter(|place| place.index >= index)
+            .min_by_key(|place| place.index);
+        let before = placed
+            .iter()
+            .filter(|place| place.index < index)
+            .max_by_key(|place| place.index);
+        match (after, before) {
+            (Some(after), _) if after.rtl => after.x + after.width,
+            (Some(after), _) => after.x,
+            (None, Some(before)) if before.rtl => before.x,
+            (None, Some(before)) => before.x + before.width,
+            (None, None) => 0.0,
+        }
+    }
+
+    // How wide `text` is at `size`.
+    pub fn text_width(&self, size: u32, text: &str) -> f64 {
+        self.layout(size, text).1
+    }
+
+    // How far `ch` reaches above the baseline at `size`.
+    pub fn glyph_top(&self, size: u32, ch: char) -> f64 {
+        self.glyph(ch)
+            .scaled(Self::scale(size))
+            .exact_bounding_box()
+            .map_or(0.0, |bounds| f64::from(-bounds.min.y))
+    }
+}
+
+impl Faces {
+    // Ask for a face that has `ch`, and add it if it's one we don't have yet. This
+    // doesn't check that it has `ch`, since fontconfig gives us its best guess either
+    // way.
+    fn find_face(&mut self, ch: char) -> Option<usize> {
+        let path = (self.find.as_ref()?)(ch)?;
+        if let Some(index) = self.paths.iter().position(|loaded| *loaded == path) {
+            return Some(index);
+        }
+        match load_face(&path) {
+            Ok(font) => {
+                log::debug!("Drawing {:?} with {:?}", ch, path);
+                self.fonts.push(font);
+                self.paths.push(path);
+                Some(self.fonts.len() - 1)
+            }
+            Err(e) => {
+                log::warn!("Failed to load {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+}
+
+fn load_face(path: &Path) -> io::Result<rusttype::Font<'static>> {
+    let bytes = std::fs::read(path)?;
+    rusttype::Font::try_from_vec(bytes)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a font file"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn falling_back_to_another_face() {
+        let lookups = Rc::new(Cell::new(0));
+        let counted = lookups.clone();
+        let font = Font::load(&fixture("DejaVuSansMono.ttf"))
+            .unwrap()
+            .with_fallback(move |_| {
+                counted.set(counted.get() + 1);
+                Some(fixture("DejaVuSans.ttf"))
+            });
+
+        // Sans Mono has no trigrams, but Sans does.
+        let (placed, width) = font.layout(20, "a☰a");
+        let faces: Vec<usize> = placed.iter().map(|place| place.face).collect();
+        assert_eq!(faces, [0, 1, 0]);
+        assert!(placed[2].x - placed[1].x > 0.0);
+        assert!(width > font.text_width(20, "aa"));
+        assert_eq!(lookups.get(), 1);
+
+        // Something nobody has is drawn by the first face, and we only look once.
+        assert_eq!(font.face_for('\u{E000}'), 0);
+        assert_eq!(font.face_for('\u{E000}'), 0);
+        assert_eq!(lookups.get(), 2);
+        // The file that was already loaded isn't loaded again.
+        assert_eq!(font.face_for('⚫'), 1);
+        assert_eq!(font.0.borrow().fonts.len(), 2);
+    }
+
+    #[test]
+    fn carets_in_right_to_left_text() {
+        let font = Font::load(&fixture("DejaVuSans.ttf")).unwrap();
+        // "Hebrew" in Hebrew, then a space and "2": the 2 is drawn on the left of it.
+        let text = "עברית 2";
+        let (placed, width) = font.layout(20, text);
+        assert_eq!(placed[0].ch, '2');
+        // The cursor starts on the right, and moves left as you type.
+        assert_eq!(Font::caret_x(&placed, 0), width);
+        let after_one = Font::caret_x(&placed, "ע".len());
+        assert!(after_one < width && after_one > width / 2.0);
+        // Before the space after the last Hebrew letter, it's on that letter's left.
+        let before_space = text.find(' ').unwrap();
+        let last_letter = placed
+            .iter()
+            .find(|place| place.index == "עברי".len())
+            .unwrap();
+        assert_eq!(Font::caret_x(&placed, before_space), last_letter.x);
+        // Left-to-right text works like it always did.
+        let (placed, width) = font.layout(20, "ab");
+        assert_eq!(Font::caret_x(&placed, 1), font.text_width(20, "a"));
+        assert_eq!(Font::caret_x(&placed, 2), width);
+    }
+}
@@ -0,0 +1,260 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Drawing in the window, with piston's graphics on top of OpenGL. This is made fresh
+// for every frame inside of window.draw_2d, since that's the only place we get a
+// Graphics to draw with. The glyph caches last between frames, in GlyphCaches.
+
+use crate::layout::intersect;
+use crate::render::{Font, Image, PlacedChar, Renderer};
+use log::warn;
+use piston_window::texture::{CreateTexture, Format};
+use piston_window::{
+    rectangle, CharacterCache, Context, DrawState, G2dTextureContext, Glyphs, Graphics, Line,
+    PistonWindow, Rectangle, Texture, TextureContext, TextureSettings,
+};
+
+// A glyph cache keeps the glyphs we've drawn in a texture, so we don't have to rasterize
+// them again every frame. Each one draws with its own copy of a single font, so there's
+// one for every face of the Font (see render/mod.rs), made the first time that face
+// draws something. Most of the time that's only ever the first one.
+pub struct GlyphCaches {
+    // For making the caches, and textures for images.
+    context: G2dTextureContext,
+    caches: Vec<Option<Glyphs>>,
+}
+
+impl GlyphCaches {
+    pub fn new(window: &mut PistonWindow) -> Self {
+        GlyphCaches {
+            context: window.create_texture_context(),
+            caches: Vec::new(),
+        }
+    }
+
+    fn get(&mut self, font: &Font, face: usize) -> &mut Glyphs {
+        if self.caches.len() <= face {
+            self.caches.resize_with(face + 1, || None);
+        }
+        let factory = &mut self.context.factory;
+        self.caches[face].get_or_insert_with(|| {
+            let context = TextureContext {
+                factory: factory.clone(),
+                encoder: factory.create_command_buffer().into(),
+            };
+            Glyphs::from_font(font.face(face), context, TextureSettings::new())
+        })
+    }
+
+    // Send the glyphs that were added this frame to the GPU.
+    pub fn flush(&mut self, device: &mut gfx_device_gl::Device) {
+        self.context.encoder.flush(device);
+        for cache in self.caches.iter_mut().flatten() {
+            cache.factory.encoder.flush(device);
+        }
+    }
+}
+
+pub struct PistonRenderer<'a, G> {
+    c: Context,
+    g: &'a mut G,
+    // The glyph caches have their own copies of the faces, but we lay the text out
+    // with `font`.
+    glyph_caches: &'a mut GlyphCaches,
+    font: &'a Font,
+    // Where we're allowed to draw, innermost last.
+    clips: Vec<[f64; 4]>,
+    draw_state: DrawState,
+}
+
+impl<'a, G> PistonRenderer<'a, G>
+where
+    G: Graphics<Texture = Texture<gfx_device_gl::Resources>>,
+{
+    pub fn new(
+        c: Context,
+        g: &'a mut G,
+        glyph_caches: &'a mut GlyphCaches,
+        font: &'a Font,
+    ) -> Self {
+        PistonRenderer {
+            c,
+            g,
+            glyph_caches,
+            font,
+            clips: Vec::new(),
+            draw_state: DrawState::default(),
+        }
+    }
+
+    // How many pixels of the framebuffer each of the pixels we lay things out in takes
+    // up. The window is sized in those (see LogicalSize in main), so on a screen with
+    // a high DPI, there are more pixels to draw into than we think there are.
+    fn pixel_scale(&self) -> f64 {
+        self.c.viewport.map_or(1.0, |viewport| {
+            f64::from(viewport.draw_size[1]) / viewport.window_size[1]
+        })
+    }
+
+    // Piston clips with OpenGL's scissor, which is in pixels of the framebuffer and
+    // counts from the bottom of the window.
+    fn update_scissor(&mut self) {
+        let clip = match self.clips.last() {
+            Some(&clip) => clip,
+            None => {
+                self.draw_state.scissor = None;
+                return;
+            }
+        };
+        let scale = self.pixel_scale();
+        let framebuffer_height = match self.c.viewport {
+            Some(viewport) => f64::from(viewport.draw_size[1]),
+            None => self.c.get_view_size()[1],
+        };
+        let left = (clip[0] * scale).round().max(0.0);
+        let right = ((clip[0] + clip[2]) * scale).round().max(left);
+        let top = (clip[1] * scale).round().max(0.0);
+        let bottom = ((clip[1] + clip[3]) * scale)
+            .round()
+            .clamp(top, framebuffer_height);
+        self.draw_state.scissor = Some([
+            left as u32,
+            (framebuffer_height - bottom) as u32,
+            (right - left) as u32,
+            (bottom - top) as u32,
+        ]);
+    }
+}
+
+impl<'a, G> Renderer for PistonRenderer<'a, G>
+where
+    G: Graphics<Texture = Texture<gfx_device_gl::Resources>>,
+{
+    fn font(&self) -> &Font {
+        self.font
+    }
+
+    fn clear(&mut self, color: [f32; 4]) {
+        self.g.clear_color(color);
+    }
+
+    fn rectangle(
+        &mut self,
+        rect: [f64; 4],
+        background: [f32; 4],
+        border: Option<([f32; 4], f64)>,
+        radius: f64,
+    ) {
+        let shape = if radius > 0.0 {
+            rectangle::Shape::Round(radius, 16)
+        } else {
+            rectangle::Shape::Square
+        };
+        let mut rectangle = Rectangle::new(background).shape(shape);
+        if let Some((color, width)) = border {
+            // A zero-width border would still be drawn as a hairline, so we skip it.
+            if width > 0.0 {
+                rectangle = rectangle.border(rectangle::Border {
+                    color,
+                    radius: width,
+                });
+            }
+        }
+        rectangle.draw(rect, &self.draw_state, self.c.transform, self.g);
+    }
+
+    fn line(&mut self, from: [f64; 2], to: [f64; 2], color: [f32; 4], width: f64) {
+        // Piston's lines are as thick as twice their "radius".
+        Line::new(color, width / 2.0).draw(
+            [from[0], from[1], to[0], to[1]],
+            &self.draw_state,
+            self.c.transform,
+            self.g,
+        );
+    }
+
+    fn placed_text(
+        &mut self,
+        placed: &[PlacedChar],
+        color: [f32; 4],
+        size: u32,
+        position: [f64; 2],
+    ) {
+        // The glyphs are rasterized at the size they end up on screen, in the
+        // framebuffer's pixels, so they're sharp however many of those there are. Piston
+        // draws in our (logical) pixels, so each glyph's rectangle is measured in those.
+        let pixel_scale = self.pixel_scale();
+        let glyph_size = (f64::from(size) * pixel_scale).round() as u32;
+        let mut image = piston_window::Image::new_color(color);
+        for &PlacedChar { ch, face, x, .. } in placed {
+            // This only fails when the glyph doesn't fit in the cache's texture. Text
+            // with a hole in it beats no window at all.
+            let glyph_cache = self.glyph_caches.get(self.font, face);
+            let character = match glyph_cache.character(glyph_size, ch) {
+                Ok(character) => character,
+                Err(e) => {
+                    warn!("Failed to draw {:?}: {:?}", ch, e);
+                    continue;
+                }
+            };
+            image = image
+                .src_rect([
+                    character.atlas_offset[0],
+                    character.atlas_offset[1],
+                    character.atlas_size[0],
+                    character.atlas_size[1],
+                ])
+                // Every face's glyphs are placed from the same baseline, so text in
+                // more than one face still lines up.
+                .rect([
+                    position[0] + x + character.left() / pixel_scale,
+                    position[1] - character.top() / pixel_scale,
+                    character.atlas_size[0] / pixel_scale,
+                    character.atlas_size[1] / pixel_scale,
+                ]);
+            image.draw(
+                character.texture,
+                &self.draw_state,
+                self.c.transform,
+                self.g,
+            );
+        }
+    }
+
+    fn image(&mut self, rect: [f64; 4], rgba: &Image) {
+        // Nothing draws many images (or the same one for long), so we don't bother
+        // keeping the texture around between frames.
+        let texture = match Texture::create(
+            &mut self.glyph_caches.context,
+            Format::Rgba8,
+            &rgba.pixels,
+            [rgba.width, rgba.height],
+            &TextureSettings::new(),
+        ) {
+            Ok(texture) => texture,
+            Err(e) => {
+                warn!("Failed to make a texture for an image: {:?}", e);
+                return;
+            }
+        };
+        piston_window::Image::new().rect(rect).draw(
+            &texture,
+            &self.draw_state,
+            self.c.transform,
+            self.g,
+        );
+    }
+
+    fn push_clip(&mut self, rect: [f64; 4]) {
+        let clip = match self.clips.last() {
+            Some(&outer) => intersect(outer, rect),
+            None => rect,
+        };
+        self.clips.push(clip);
+        self.update_scissor();
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+        self.update_scissor();
+    }
+}
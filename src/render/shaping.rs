@@ -0,0 +1,348 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Getting text from the order it's in (logical order) to the order it's drawn in
+// (visual order), for Font::layout. Hebrew only needs reordering, since the
+// Unicode Bidirectional Algorithm (in unicode-bidi) runs right-to-left text from right
+// to left, and left-to-right text in it (like numbers or "Firefox") the other way.
+//
+// Arabic letters also change shape depending on whether they join the letters next to
+// them. Fonts do that with OpenType tables, which would need a real shaper like
+// HarfBuzz, and glyphs that aren't chars, which piston's glyph cache can't draw. But
+// Unicode has every shape of the common letters as a char of its own (the
+// "presentation forms"), and most fonts that have Arabic have those too, so we swap
+// those in instead. That doesn't do the fancier ligatures some fonts have, but
+// it's what you'd expect to read.
+
+use unicode_bidi::{bidi_class, BidiClass, ParagraphBidiInfo};
+
+// A char of text, ready to draw.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapedChar {
+    // Where it came from in the text, as a byte index.
+    pub index: usize,
+    // What to draw, which isn't always the char that's in the text.
+    pub ch: char,
+    pub rtl: bool,
+}
+
+// `text`, shaped and in visual order. Some chars disappear into the one before them
+// (see LAM_ALEF), so there can be fewer of these than there were chars, but every
+// one that's left is there once.
+pub fn shape(text: &str) -> Vec<ShapedChar> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    // Nearly everything is left-to-right, and then there's nothing to do.
+    if !chars.iter().any(|&(_, ch)| is_rtl(ch)) {
+        return chars
+            .into_iter()
+            .map(|(index, ch)| ShapedChar {
+                index,
+                ch,
+                rtl: false,
+            })
+            .collect();
+    }
+
+    let shaped = join_arabic(&chars);
+    let bidi = ParagraphBidiInfo::new(text, None);
+    let levels = bidi.reordered_levels_per_char(0..text.len());
+    let mut visual: Vec<ShapedChar> = ParagraphBidiInfo::reorder_visual(&levels)
+        .into_iter()
+        .filter_map(|i| {
+            let (index, ch) = shaped[i]?;
+            let rtl = levels[i].is_rtl();
+            Some(ShapedChar {
+                index,
+                ch: if rtl { mirror(ch) } else { ch },
+                rtl,
+            })
+        })
+        .filter(|shaped| !is_bidi_control(shaped.ch))
+        .collect();
+    keep_marks_after_bases(&mut visual);
+    visual
+}
+
+// Whether `ch` makes text around it go right-to-left.
+fn is_rtl(ch: char) -> bool {
+    matches!(
+        bidi_class(ch),
+        BidiClass::R | BidiClass::AL | BidiClass::RLE | BidiClass::RLO | BidiClass::RLI
+    )
+}
+
+// The invisible chars that only tell the bidi algorithm what to do. Fonts don't
+// usually have anything for them, so we don't draw them.
+fn is_bidi_control(ch: char) -> bool {
+    matches!(ch, '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+// Brackets and such point the other way in right-to-left text, so "(" opens on the
+// right.
+fn mirror(ch: char) -> char {
+    match ch {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        '«' => '»',
+        '»' => '«',
+        '‹' => '›',
+        '›' => '‹',
+        _ => ch,
+    }
+}
+
+// Accents and vowel marks (like Arabic harakat) are drawn over the char before them,
+// which in right-to-left text ends up after them once it's reversed. Fonts place them
+// expecting to come after it, like in left-to-right text, so we put them back there.
+fn keep_marks_after_bases(visual: &mut [ShapedChar]) {
+    let mut start = 0;
+    while start < visual.len() {
+        let marks = visual[start..]
+            .iter()
+            .take_while(|shaped| shaped.rtl && is_transparent(shaped.ch))
+            .count();
+        if marks > 0 && start + marks < visual.len() && visual[start + marks].rtl {
+            visual[start..=start + marks].reverse();
+        }
+        start += marks + 1;
+    }
+}
+
+// How an Arabic letter joins the letters next to it.
+#[derive(Clone, Copy, PartialEq)]
+enum Joining {
+    // To the letters on both sides, like beh.
+    Dual,
+    // Only to the letter before it (on its right), like alef.
+    Right,
+    // Marks that sit on top of letters and don't get in the way of joining.
+    Transparent,
+    // Anything else, which letters don't join to.
+    None,
+}
+
+fn joining(ch: char) -> Joining {
+    if is_transparent(ch) {
+        return Joining::Transparent;
+    }
+    // Tatweel (the line that stretches words out) and the zero width joiner join to
+    // anything that'll join to them.
+    if ch == '\u{0640}' || ch == '\u{200D}' {
+        return Joining::Dual;
+    }
+    match forms(ch) {
+        Some([_, _, NONE, _]) => Joining::Right,
+        Some(_) => Joining::Dual,
+        None => Joining::None,
+    }
+}
+
+fn is_transparent(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{0610}'..='\u{061A}'
+            | '\u{064B}'..='\u{065F}'
+            | '\u{0670}'
+            | '\u{06D6}'..='\u{06DC}'
+            | '\u{06DF}'..='\u{06E4}'
+            | '\u{06E7}'..='\u{06E8}'
+            | '\u{06EA}'..='\u{06ED}'
+    )
+}
+
+// Swap every Arabic letter in `chars` for the form that joins the way it has to, in
+// the same (logical) order. A char that's become part of the one before it is None.
+fn join_arabic(chars: &[(usize, char)]) -> Vec<Option<(usize, char)>> {
+    // Which letter each one is next to, skipping over marks.
+    let neighbour = |from: usize, step: isize| {
+        let mut i = from as isize + step;
+        while i >= 0 && (i as usize) < chars.len() {
+            let kind = joining(chars[i as usize].1);
+            if kind != Joining::Transparent {
+                return kind;
+            }
+            i += step;
+        }
+        Joining::None
+    };
+
+    let mut shaped: Vec<Option<(usize, char)>> = chars.iter().copied().map(Some).collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let (index, ch) = chars[i];
+        let kind = joining(ch);
+        let after_joiner = neighbour(i, -1) == Joining::Dual;
+        if let (Some(next), true) = (chars.get(i + 1), ch == LAM) {
+            if let Some(&(_, ligature)) = LAM_ALEF.iter().find(|(alef, _)| *alef == next.1) {
+                // The ligature only has isolated and final forms, since alef doesn't
+                // join to what's after it.
+                let ligature = if after_joiner {
+                    char::from_u32(ligature as u32 + 1).unwrap_or(ligature)
+                } else {
+                    ligature
+                };
+                shaped[i] = Some((index, ligature));
+                shaped[i + 1] = None;
+                i += 2;
+                continue;
+            }
+        }
+        if let Some([isolated, final_, initial, medial]) = forms(ch) {
+            let joins_before = after_joiner;
+            let joins_after =
+                kind == Joining::Dual && matches!(neighbour(i, 1), Joining::Dual | Joining::Right);
+            let form = match (joins_before, joins_after) {
+                (true, true) => medial,
+                (true, false) => final_,
+                (false, true) => initial,
+                (false, false) => isolated,
+            };
+            if form != NONE {
+                shaped[i] = Some((index, form));
+            }
+        }
+        i += 1;
+    }
+    shaped
+}
+
+const NONE: char = '\0';
+const LAM: char = '\u{0644}';
+
+// Lam followed by alef is always written as a ligature. These are the isolated forms
+// of it for each alef, and the final form is the next char.
+const LAM_ALEF: [(char, char); 4] = [
+    ('\u{0622}', '\u{FEF5}'),
+    ('\u{0623}', '\u{FEF7}'),
+    ('\u{0625}', '\u{FEF9}'),
+    ('\u{0627}', '\u{FEFB}'),
+];
+
+// The isolated, final, initial and medial presentation forms of `letter`. Letters that
+// only join to the one before them don't have the last two.
+fn forms(letter: char) -> Option<[char; 4]> {
+    ARABIC_FORMS
+        .binary_search_by_key(&letter, |&(ch, _)| ch)
+        .ok()
+        .map(|i| ARABIC_FORMS[i].1)
+}
+
+// In order, for forms.
+#[rustfmt::skip]
+const ARABIC_FORMS: &[(char, [char; 4])] = &[
+    ('\u{0622}', ['\u{FE81}', '\u{FE82}', NONE, NONE]), // alef with madda above
+    ('\u{0623}', ['\u{FE83}', '\u{FE84}', NONE, NONE]), // alef with hamza above
+    ('\u{0624}', ['\u{FE85}', '\u{FE86}', NONE, NONE]), // waw with hamza above
+    ('\u{0625}', ['\u{FE87}', '\u{FE88}', NONE, NONE]), // alef with hamza below
+    ('\u{0626}', ['\u{FE89}', '\u{FE8A}', '\u{FE8B}', '\u{FE8C}']), // yeh with hamza above
+    ('\u{0627}', ['\u{FE8D}', '\u{FE8E}', NONE, NONE]), // alef
+    ('\u{0628}', ['\u{FE8F}', '\u{FE90}', '\u{FE91}', '\u{FE92}']), // beh
+    ('\u{0629}', ['\u{FE93}', '\u{FE94}', NONE, NONE]), // teh marbuta
+    ('\u{062A}', ['\u{FE95}', '\u{FE96}', '\u{FE97}', '\u{FE98}']), // teh
+    ('\u{062B}', ['\u{FE99}', '\u{FE9A}', '\u{FE9B}', '\u{FE9C}']), // theh
+    ('\u{062C}', ['\u{FE9D}', '\u{FE9E}', '\u{FE9F}', '\u{FEA0}']), // jeem
+    ('\u{062D}', ['\u{FEA1}', '\u{FEA2}', '\u{FEA3}', '\u{FEA4}']), // hah
+    ('\u{062E}', ['\u{FEA5}', '\u{FEA6}', '\u{FEA7}', '\u{FEA8}']), // khah
+    ('\u{062F}', ['\u{FEA9}', '\u{FEAA}', NONE, NONE]), // dal
+    ('\u{0630}', ['\u{FEAB}', '\u{FEAC}', NONE, NONE]), // thal
+    ('\u{0631}', ['\u{FEAD}', '\u{FEAE}', NONE, NONE]), // reh
+    ('\u{0632}', ['\u{FEAF}', '\u{FEB0}', NONE, NONE]), // zain
+    ('\u{0633}', ['\u{FEB1}', '\u{FEB2}', '\u{FEB3}', '\u{FEB4}']), // seen
+    ('\u{0634}', ['\u{FEB5}', '\u{FEB6}', '\u{FEB7}', '\u{FEB8}']), // sheen
+    ('\u{0635}', ['\u{FEB9}', '\u{FEBA}', '\u{FEBB}', '\u{FEBC}']), // sad
+    ('\u{0636}', ['\u{FEBD}', '\u{FEBE}', '\u{FEBF}', '\u{FEC0}']), // dad
+    ('\u{0637}', ['\u{FEC1}', '\u{FEC2}', '\u{FEC3}', '\u{FEC4}']), // tah
+    ('\u{0638}', ['\u{FEC5}', '\u{FEC6}', '\u{FEC7}', '\u{FEC8}']), // zah
+    ('\u{0639}', ['\u{FEC9}', '\u{FECA}', '\u{FECB}', '\u{FECC}']), // ain
+    ('\u{063A}', ['\u{FECD}', '\u{FECE}', '\u{FECF}', '\u{FED0}']), // ghain
+    ('\u{0641}', ['\u{FED1}', '\u{FED2}', '\u{FED3}', '\u{FED4}']), // feh
+    ('\u{0642}', ['\u{FED5}', '\u{FED6}', '\u{FED7}', '\u{FED8}']), // qaf
+    ('\u{0643}', ['\u{FED9}', '\u{FEDA}', '\u{FEDB}', '\u{FEDC}']), // kaf
+    ('\u{0644}', ['\u{FEDD}', '\u{FEDE}', '\u{FEDF}', '\u{FEE0}']), // lam
+    ('\u{0645}', ['\u{FEE1}', '\u{FEE2}', '\u{FEE3}', '\u{FEE4}']), // meem
+    ('\u{0646}', ['\u{FEE5}', '\u{FEE6}', '\u{FEE7}', '\u{FEE8}']), // noon
+    ('\u{0647}', ['\u{FEE9}', '\u{FEEA}', '\u{FEEB}', '\u{FEEC}']), // heh
+    ('\u{0648}', ['\u{FEED}', '\u{FEEE}', NONE, NONE]), // waw
+    ('\u{0649}', ['\u{FEEF}', '\u{FEF0}', NONE, NONE]), // alef maksura
+    ('\u{064A}', ['\u{FEF1}', '\u{FEF2}', '\u{FEF3}', '\u{FEF4}']), // yeh
+    ('\u{0671}', ['\u{FB50}', '\u{FB51}', NONE, NONE]), // alef wasla
+    ('\u{0679}', ['\u{FB66}', '\u{FB67}', '\u{FB68}', '\u{FB69}']), // tteh
+    ('\u{067A}', ['\u{FB5E}', '\u{FB5F}', '\u{FB60}', '\u{FB61}']), // tteheh
+    ('\u{067B}', ['\u{FB52}', '\u{FB53}', '\u{FB54}', '\u{FB55}']), // beeh
+    ('\u{067E}', ['\u{FB56}', '\u{FB57}', '\u{FB58}', '\u{FB59}']), // peh
+    ('\u{067F}', ['\u{FB62}', '\u{FB63}', '\u{FB64}', '\u{FB65}']), // teheh
+    ('\u{0680}', ['\u{FB5A}', '\u{FB5B}', '\u{FB5C}', '\u{FB5D}']), // beheh
+    ('\u{0683}', ['\u{FB76}', '\u{FB77}', '\u{FB78}', '\u{FB79}']), // nyeh
+    ('\u{0684}', ['\u{FB72}', '\u{FB73}', '\u{FB74}', '\u{FB75}']), // dyeh
+    ('\u{0686}', ['\u{FB7A}', '\u{FB7B}', '\u{FB7C}', '\u{FB7D}']), // tcheh
+    ('\u{0687}', ['\u{FB7E}', '\u{FB7F}', '\u{FB80}', '\u{FB81}']), // tcheheh
+    ('\u{0688}', ['\u{FB88}', '\u{FB89}', NONE, NONE]), // ddal
+    ('\u{068C}', ['\u{FB84}', '\u{FB85}', NONE, NONE]), // dahal
+    ('\u{068D}', ['\u{FB82}', '\u{FB83}', NONE, NONE]), // ddahal
+    ('\u{068E}', ['\u{FB86}', '\u{FB87}', NONE, NONE]), // dul
+    ('\u{0691}', ['\u{FB8C}', '\u{FB8D}', NONE, NONE]), // rreh
+    ('\u{0698}', ['\u{FB8A}', '\u{FB8B}', NONE, NONE]), // jeh
+    ('\u{06A4}', ['\u{FB6A}', '\u{FB6B}', '\u{FB6C}', '\u{FB6D}']), // veh
+    ('\u{06A6}', ['\u{FB6E}', '\u{FB6F}', '\u{FB70}', '\u{FB71}']), // peheh
+    ('\u{06A9}', ['\u{FB8E}', '\u{FB8F}', '\u{FB90}', '\u{FB91}']), // keheh
+    ('\u{06AD}', ['\u{FBD3}', '\u{FBD4}', '\u{FBD5}', '\u{FBD6}']), // ng
+    ('\u{06AF}', ['\u{FB92}', '\u{FB93}', '\u{FB94}', '\u{FB95}']), // gaf
+    ('\u{06B1}', ['\u{FB9A}', '\u{FB9B}', '\u{FB9C}', '\u{FB9D}']), // ngoeh
+    ('\u{06B3}', ['\u{FB96}', '\u{FB97}', '\u{FB98}', '\u{FB99}']), // gueh
+    ('\u{06BA}', ['\u{FB9E}', '\u{FB9F}', NONE, NONE]), // noon ghunna
+    ('\u{06BB}', ['\u{FBA0}', '\u{FBA1}', '\u{FBA2}', '\u{FBA3}']), // rnoon
+    ('\u{06BE}', ['\u{FBAA}', '\u{FBAB}', '\u{FBAC}', '\u{FBAD}']), // heh doachashmee
+    ('\u{06C0}', ['\u{FBA4}', '\u{FBA5}', NONE, NONE]), // heh with yeh above
+    ('\u{06C1}', ['\u{FBA6}', '\u{FBA7}', '\u{FBA8}', '\u{FBA9}']), // heh goal
+    ('\u{06C5}', ['\u{FBE0}', '\u{FBE1}', NONE, NONE]), // kirghiz oe
+    ('\u{06C6}', ['\u{FBD9}', '\u{FBDA}', NONE, NONE]), // oe
+    ('\u{06C7}', ['\u{FBD7}', '\u{FBD8}', NONE, NONE]), // u
+    ('\u{06C8}', ['\u{FBDB}', '\u{FBDC}', NONE, NONE]), // yu
+    ('\u{06C9}', ['\u{FBE2}', '\u{FBE3}', NONE, NONE]), // kirghiz yu
+    ('\u{06CB}', ['\u{FBDE}', '\u{FBDF}', NONE, NONE]), // ve
+    ('\u{06CC}', ['\u{FBFC}', '\u{FBFD}', '\u{FBFE}', '\u{FBFF}']), // farsi yeh
+    ('\u{06D0}', ['\u{FBE4}', '\u{FBE5}', '\u{FBE6}', '\u{FBE7}']), // e
+    ('\u{06D2}', ['\u{FBAE}', '\u{FBAF}', NONE, NONE]), // yeh barree
+    ('\u{06D3}', ['\u{FBB0}', '\u{FBB1}', NONE, NONE]), // yeh barree with hamza above
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drawn(text: &str) -> String {
+        shape(text).iter().map(|shaped| shaped.ch).collect()
+    }
+
+    #[test]
+    fn reordering_hebrew() {
+        // "Hello (world)" in Hebrew, with the Latin name of a program in the middle.
+        let text = "שלום Firefox (עולם)";
+        assert_eq!(drawn(text), "(םלוע) Firefox םולש");
+        let shaped = shape(text);
+        // The first char drawn is the last one typed, and it knows where it's from.
+        assert_eq!(shaped[0].index, text.rfind(')').unwrap());
+        assert!(shaped[0].rtl);
+        assert!(!shaped[7].rtl);
+        // Plain left-to-right text is left alone.
+        assert_eq!(drawn("Firefox (beta)"), "Firefox (beta)");
+    }
+
+    #[test]
+    fn joining_arabic() {
+        // "Arabic": alef doesn't join to what's after it, so the word starts over there.
+        assert_eq!(drawn("عربي"), "\u{FEF2}\u{FE91}\u{FEAE}\u{FECB}",);
+        // Lam and alef become one char, with the alef's index gone.
+        let shaped = shape("سلام");
+        assert_eq!(shaped.len(), 3);
+        assert_eq!(shaped[1].ch, '\u{FEFC}');
+        assert_eq!(shaped[1].index, "س".len());
+        // A mark doesn't stop letters joining, and stays after its letter.
+        assert_eq!(drawn("بَب"), "\u{FE90}\u{FE91}\u{064E}");
+    }
+}
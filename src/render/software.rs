@@ -0,0 +1,319 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Drawing into memory on the CPU, with tiny-skia. This is the software-render feature,
+// and the snapshot tests (see snapshot.rs) draw with it, since they have to run without
+// a GPU or an X server and come out the same on every machine. We copy piston's way of drawing things (borders centered on the
+// edge, glyphs from rusttype at Font::scale), so a snapshot looks like the window does.
+//
+// Like the window on a screen with a high DPI, the picture can have more pixels than
+// the launcher lays things out in (window.scale). Everything is drawn that much
+// bigger, text included, instead of being blown up afterwards.
+
+use crate::layout::intersect;
+use crate::render::{Font, Image, PlacedChar, Renderer};
+use tiny_skia::{
+    Color, FillRule, FilterQuality, IntSize, LineCap, LineJoin, Mask, Paint, Path as SkPath,
+    PathBuilder, Pixmap, PixmapPaint, PremultipliedColorU8, Rect, Stroke, Transform,
+};
+
+pub struct SoftwareRenderer {
+    pixmap: Pixmap,
+    font: Font,
+    // How many pixels of the picture each of our pixels is.
+    scale: f32,
+    // Where we're allowed to draw, innermost last, and a mask of the innermost one
+    // for tiny-skia.
+    clips: Vec<[f64; 4]>,
+    mask: Option<Mask>,
+}
+
+// The window always draws with piston, so outside of the tests nothing makes one yet.
+#[cfg_attr(not(test), allow(dead_code))]
+impl SoftwareRenderer {
+    // A picture of something `width` by `height`, drawn `scale` times as big. None if
+    // it comes out with no pixels.
+    pub fn new(width: u32, height: u32, scale: f64, font: Font) -> Option<Self> {
+        let scaled = |length: u32| (f64::from(length) * scale).ceil() as u32;
+        Some(SoftwareRenderer {
+            pixmap: Pixmap::new(scaled(width), scaled(height))?,
+            font,
+            scale: scale as f32,
+            clips: Vec::new(),
+            mask: None,
+        })
+    }
+
+    // What's been drawn so far.
+    pub fn pixmap(&self) -> &Pixmap {
+        &self.pixmap
+    }
+
+    // From where we lay things out to pixels of the picture.
+    fn transform(&self) -> Transform {
+        Transform::from_scale(self.scale, self.scale)
+    }
+
+    fn update_mask(&mut self) {
+        let transform = self.transform();
+        self.mask = self.clips.last().map(|&clip| {
+            let mut mask = Mask::new(self.pixmap.width(), self.pixmap.height())
+                .expect("The mask is the size of the pixmap, which isn't empty");
+            if let Some(path) = rect_path(clip, 0.0) {
+                mask.fill_path(&path, FillRule::Winding, false, transform);
+            }
+            mask
+        });
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    fn font(&self) -> &Font {
+        &self.font
+    }
+
+    fn clear(&mut self, color: [f32; 4]) {
+        self.pixmap.fill(to_color(color));
+    }
+
+    fn rectangle(
+        &mut self,
+        rect: [f64; 4],
+        background: [f32; 4],
+        border: Option<([f32; 4], f64)>,
+        radius: f64,
+    ) {
+        let path = match rect_path(rect, radius) {
+            Some(path) => path,
+            None => return,
+        };
+        self.pixmap.fill_path(
+            &path,
+            &paint(background),
+            FillRule::Winding,
+            self.transform(),
+            self.mask.as_ref(),
+        );
+        if let Some((color, width)) = border {
+            if width > 0.0 {
+                let stroke = Stroke {
+                    width: (width * 2.0) as f32,
+                    line_join: LineJoin::Miter,
+                    ..Stroke::default()
+                };
+                self.pixmap.stroke_path(
+                    &path,
+                    &paint(color),
+                    &stroke,
+                    self.transform(),
+                    self.mask.as_ref(),
+                );
+            }
+        }
+    }
+
+    fn line(&mut self, from: [f64; 2], to: [f64; 2], color: [f32; 4], width: f64) {
+        let mut builder = PathBuilder::new();
+        builder.move_to(from[0] as f32, from[1] as f32);
+        builder.line_to(to[0] as f32, to[1] as f32);
+        if let Some(path) = builder.finish() {
+            let stroke = Stroke {
+                width: width as f32,
+                line_cap: LineCap::Square,
+                ..Stroke::default()
+            };
+            self.pixmap.stroke_path(
+                &path,
+                &paint(color),
+                &stroke,
+                self.transform(),
+                self.mask.as_ref(),
+            );
+        }
+    }
+
+    fn placed_text(
+        &mut self,
+        placed: &[PlacedChar],
+        color: [f32; 4],
+        size: u32,
+        position: [f64; 2],
+    ) {
+        let font_scale = Font::scale(size);
+        let scale = rusttype::Scale {
+            x: font_scale.x * self.scale,
+            y: font_scale.y * self.scale,
+        };
+        for &PlacedChar { ch, x, .. } in placed {
+            let glyph = self
+                .font
+                .glyph(ch)
+                .scaled(scale)
+                .positioned(rusttype::point(
+                    (position[0] + x) as f32 * self.scale,
+                    position[1] as f32 * self.scale,
+                ));
+            let bounds = match glyph.pixel_bounding_box() {
+                Some(bounds) => bounds,
+                // Spaces and the like.
+                None => continue,
+            };
+            // Draw the glyph on its own and put that on top of everything else, which
+            // takes care of the blending and the clipping for us.
+            let mut glyph_pixmap = match Pixmap::new(bounds.width() as u32, bounds.height() as u32)
+            {
+                Some(glyph_pixmap) => glyph_pixmap,
+                None => continue,
+            };
+            let width = glyph_pixmap.width();
+            let pixels = glyph_pixmap.pixels_mut();
+            glyph.draw(|gx, gy, coverage| {
+                pixels[(gy * width + gx) as usize] = premultiply(color, coverage);
+            });
+            self.pixmap.draw_pixmap(
+                bounds.min.x,
+                bounds.min.y,
+                glyph_pixmap.as_ref(),
+                &PixmapPaint::default(),
+                Transform::identity(),
+                self.mask.as_ref(),
+            );
+        }
+    }
+
+    fn image(&mut self, rect: [f64; 4], rgba: &Image) {
+        let size = match IntSize::from_wh(rgba.width, rgba.height) {
+            Some(size) => size,
+            None => return,
+        };
+        // tiny-skia wants its pixels premultiplied.
+        let pixels = rgba
+            .pixels
+            .chunks_exact(4)
+            .flat_map(|pixel| {
+                let alpha = u16::from(pixel[3]);
+                let channel = |value: u8| (u16::from(value) * alpha / 255) as u8;
+                [
+                    channel(pixel[0]),
+                    channel(pixel[1]),
+                    channel(pixel[2]),
+                    pixel[3],
+                ]
+            })
+            .collect();
+        let source = match Pixmap::from_vec(pixels, size) {
+            Some(source) => source,
+            None => return,
+        };
+        let transform = Transform::from_row(
+            (rect[2] / f64::from(rgba.width)) as f32,
+            0.0,
+            0.0,
+            (rect[3] / f64::from(rgba.height)) as f32,
+            rect[0] as f32,
+            rect[1] as f32,
+        )
+        .post_concat(self.transform());
+        self.pixmap.draw_pixmap(
+            0,
+            0,
+            source.as_ref(),
+            &PixmapPaint {
+                quality: FilterQuality::Bilinear,
+                ..PixmapPaint::default()
+            },
+            transform,
+            self.mask.as_ref(),
+        );
+    }
+
+    fn push_clip(&mut self, rect: [f64; 4]) {
+        let clip = match self.clips.last() {
+            Some(&outer) => intersect(outer, rect),
+            None => rect,
+        };
+        self.clips.push(clip);
+        self.update_mask();
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+        self.update_mask();
+    }
+}
+
+fn to_color(color: [f32; 4]) -> Color {
+    let [r, g, b, a] = color.map(|channel| channel.clamp(0.0, 1.0));
+    Color::from_rgba(r, g, b, a).expect("The channels were clamped")
+}
+
+fn paint(color: [f32; 4]) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(to_color(color));
+    paint
+}
+
+fn premultiply(color: [f32; 4], coverage: f32) -> PremultipliedColorU8 {
+    let alpha = (color[3] * coverage).clamp(0.0, 1.0);
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * alpha * 255.0).round() as u8;
+    PremultipliedColorU8::from_rgba(
+        channel(color[0]),
+        channel(color[1]),
+        channel(color[2]),
+        (alpha * 255.0).round() as u8,
+    )
+    .expect("The color channels can't be more than alpha")
+}
+
+// The outline of `rect`, with its corners rounded by `radius` like piston's
+// Shape::Round. None if it's empty.
+fn rect_path(rect: [f64; 4], radius: f64) -> Option<SkPath> {
+    let [x, y, width, height] = rect.map(|value| value as f32);
+    let radius = (radius as f32).min(width / 2.0).min(height / 2.0);
+    if radius <= 0.0 {
+        return Rect::from_xywh(x, y, width, height).map(PathBuilder::from_rect);
+    }
+
+    // How far along the edge the control points of a cubic go to make a quarter circle.
+    let handle = radius * 0.552_284_8;
+    let (right, bottom) = (x + width, y + height);
+    let mut builder = PathBuilder::new();
+    builder.move_to(x + radius, y);
+    builder.line_to(right - radius, y);
+    builder.cubic_to(
+        right - radius + handle,
+        y,
+        right,
+        y + radius - handle,
+        right,
+        y + radius,
+    );
+    builder.line_to(right, bottom - radius);
+    builder.cubic_to(
+        right,
+        bottom - radius + handle,
+        right - radius + handle,
+        bottom,
+        right - radius,
+        bottom,
+    );
+    builder.line_to(x + radius, bottom);
+    builder.cubic_to(
+        x + radius - handle,
+        bottom,
+        x,
+        bottom - radius + handle,
+        x,
+        bottom - radius,
+    );
+    builder.line_to(x, y + radius);
+    builder.cubic_to(
+        x,
+        y + radius - handle,
+        x + radius - handle,
+        y,
+        x + radius,
+        y,
+    );
+    builder.close();
+    builder.finish()
+}
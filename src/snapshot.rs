@@ -0,0 +1,467 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Snapshot tests. We put widgets through a script of input events, draw them with the
+// software renderer (see render/software.rs) and compare the picture with a PNG in
+// tests/snapshots. After changing how something looks on purpose, run
+//
+//     UPDATE_SNAPSHOTS=1 cargo test
+//
+// and check that the PNGs that changed look right before committing them. When a
+// snapshot doesn't match, what we drew instead ends up in target/snapshots.
+//
+// Everything is drawn with the font in tests/fixtures, so the pictures don't depend
+// on what fonts happen to be installed.
+//
+// Drawing needs the software-render feature. The scenes, fonts and events in here don't,
+// so the other tests use them either way, but without it most of them go unused.
+#![cfg_attr(not(feature = "software-render"), allow(dead_code))]
+
+use crate::application_launcher::ApplicationLauncher;
+use crate::configuration::{Config, Mode};
+use crate::history::History;
+use crate::input::{Keybindings, Keyboard, Route};
+use crate::render::Font;
+#[cfg(feature = "software-render")]
+use crate::render::{software::SoftwareRenderer, Renderer};
+use crate::select::EntryCallback;
+use crate::theme::Theme;
+use crate::widgets::Widget;
+use piston_window::*;
+use std::path::Path;
+#[cfg(feature = "software-render")]
+use std::path::PathBuf;
+#[cfg(feature = "software-render")]
+use tiny_skia::Pixmap;
+
+// How far apart (out of 255) a channel can be before the pixel counts as different.
+// Antialiasing doesn't come out exactly the same on every CPU.
+#[cfg(feature = "software-render")]
+const CHANNEL_TOLERANCE: u8 = 8;
+// How many pixels can be different before the snapshot doesn't match. A single glyph
+// that changed is well over this.
+#[cfg(feature = "software-render")]
+const MAX_DIFFERENT_PIXELS: usize = 20;
+
+fn manifest_dir() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+pub fn test_font() -> Font {
+    Font::load(&manifest_dir().join("tests/fixtures/DejaVuSansMono.ttf"))
+        .expect("Failed to load the test font")
+}
+
+// The test font, with DejaVu Sans to fall back on instead of whatever fontconfig
+// would find.
+pub fn test_font_with_fallback() -> Font {
+    test_font().with_fallback(|_| Some(manifest_dir().join("tests/fixtures/DejaVuSans.ttf")))
+}
+
+// dmenu mode, so the entries are just names, with history turned off so nothing
+// from the machine running the tests gets in.
+pub fn test_config() -> Config {
+    Config {
+        mode: Mode::Dmenu,
+        history_size: 0,
+        ..Config::default()
+    }
+}
+
+pub fn entries(names: &[&str]) -> Vec<(String, EntryCallback)> {
+    names
+        .iter()
+        .map(|name| {
+            let callback: EntryCallback = Box::new(|| Ok(()));
+            (name.to_string(), callback)
+        })
+        .collect()
+}
+
+// Events like the window sends them, to put in a script.
+
+pub fn key(key: Key, state: ButtonState) -> Event {
+    Event::Input(
+        Input::Button(ButtonArgs {
+            state,
+            button: Button::Keyboard(key),
+            scancode: None,
+        }),
+        None,
+    )
+}
+
+pub fn typing(text: &str) -> Vec<Event> {
+    vec![Event::Input(Input::Text(text.to_string()), None)]
+}
+
+pub fn pressing(pressed: Key) -> Vec<Event> {
+    vec![
+        key(pressed, ButtonState::Press),
+        key(pressed, ButtonState::Release),
+    ]
+}
+
+pub fn pressing_with(modifier: Key, pressed: Key) -> Vec<Event> {
+    let mut events = vec![key(modifier, ButtonState::Press)];
+    events.extend(pressing(pressed));
+    events.push(key(modifier, ButtonState::Release));
+    events
+}
+
+pub fn moving_mouse_to(position: [f64; 2]) -> Vec<Event> {
+    vec![Event::Input(
+        Input::Move(Motion::MouseCursor(position)),
+        None,
+    )]
+}
+
+// The whole window, the way main runs and draws it.
+pub struct Scene {
+    pub launcher: ApplicationLauncher,
+    pub config: Config,
+    pub theme: Theme,
+    pub font: Font,
+}
+
+impl Scene {
+    pub fn new(names: &[&str]) -> Self {
+        let config = test_config();
+        let history = History::load(config.mode, config.history_size);
+        Scene {
+            launcher: ApplicationLauncher::with_entries(&config, None, entries(names), history),
+            config,
+            theme: Theme::default(),
+            font: test_font(),
+        }
+    }
+
+    fn size(&self) -> [f64; 2] {
+        [
+            f64::from(self.config.window.width),
+            f64::from(self.config.window.height),
+        ]
+    }
+
+    // Like main, everything is laid out again before every event.
+    pub fn feed(&mut self, script: Vec<Event>) {
+        let [width, height] = self.size();
+        for ev in script {
+            self.launcher
+                .arrange([0.0, 0.0, width, height], &self.config, &self.font);
+            self.launcher.handle_event(&ev);
+        }
+    }
+
+    #[cfg(feature = "software-render")]
+    pub fn render(&mut self) -> Pixmap {
+        let [width, height] = self.size();
+        self.launcher
+            .arrange([0.0, 0.0, width, height], &self.config, &self.font);
+        let scale = self.config.window.scale.unwrap_or(1.0);
+        let mut renderer =
+            SoftwareRenderer::new(width as u32, height as u32, scale, self.font.clone())
+                .expect("The window has a size");
+        crate::draw_window(
+            &self.launcher,
+            &self.config,
+            &self.theme,
+            &mut renderer,
+            [width, height],
+        );
+        renderer.pixmap().clone()
+    }
+}
+
+// Put a single widget through a script, behind the default keybindings like it would
+// be in the launcher. It has to be arranged first, for the mouse.
+pub fn feed<W: Widget>(widget: &mut W, script: Vec<Event>) {
+    let mut keyboard = Keyboard::new(Keybindings::default());
+    for ev in script {
+        match keyboard.route(&ev) {
+            Route::Action(action) => widget.handle_action(action),
+            Route::Widgets => widget.handle_event(&ev),
+            Route::Nowhere => {}
+        }
+    }
+}
+
+// Draw a single widget wherever it was arranged, on top of the window's background.
+#[cfg(feature = "software-render")]
+pub fn render_widget<W: Widget>(
+    widget: &W,
+    config: &Config,
+    theme: &Theme,
+    size: [u32; 2],
+) -> Pixmap {
+    let mut renderer =
+        SoftwareRenderer::new(size[0], size[1], 1.0, test_font()).expect("The picture has a size");
+    renderer.clear(theme.window.background.0);
+    widget.draw(config, theme, &mut renderer);
+    renderer.pixmap().clone()
+}
+
+#[cfg(feature = "software-render")]
+pub fn assert_snapshot(name: &str, actual: &Pixmap) {
+    let path = manifest_dir()
+        .join("tests/snapshots")
+        .join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        save(actual, &path);
+        return;
+    }
+
+    let difference = match Pixmap::load_png(&path) {
+        Ok(expected)
+            if expected.width() != actual.width() || expected.height() != actual.height() =>
+        {
+            format!(
+                "is {}x{} instead of {}x{}",
+                actual.width(),
+                actual.height(),
+                expected.width(),
+                expected.height()
+            )
+        }
+        Ok(expected) => {
+            let different_pixels = expected
+                .data()
+                .chunks_exact(4)
+                .zip(actual.data().chunks_exact(4))
+                .filter(|(expected, actual)| {
+                    expected
+                        .iter()
+                        .zip(actual.iter())
+                        .any(|(expected, actual)| expected.abs_diff(*actual) > CHANNEL_TOLERANCE)
+                })
+                .count();
+            if different_pixels <= MAX_DIFFERENT_PIXELS {
+                return;
+            }
+            format!("has {} different pixels", different_pixels)
+        }
+        Err(e) => format!("has no snapshot to compare with ({})", e),
+    };
+
+    let actual_path = snapshot_output_dir().join(format!("{}.png", name));
+    save(actual, &actual_path);
+    panic!(
+        "Snapshot {} {}. What we drew is in {:?}; if it's right, run the tests with \
+         UPDATE_SNAPSHOTS=1 to make it the new snapshot.",
+        name, difference, actual_path
+    );
+}
+
+#[cfg(feature = "software-render")]
+fn snapshot_output_dir() -> PathBuf {
+    manifest_dir().join("target").join("snapshots")
+}
+
+#[cfg(feature = "software-render")]
+fn save(pixmap: &Pixmap, path: &Path) {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).expect("Failed to make the snapshot directory");
+    }
+    pixmap
+        .save_png(path)
+        .unwrap_or_else(|e| panic!("Failed to save {:?}: {}", path, e));
+}
+
+#[cfg(feature = "software-render")]
+mod tests {
+    use super::*;
+    use crate::configuration::{Ellipsis, ViMode};
+    use crate::recording::{Header, Recording};
+    use crate::render::Image;
+    use crate::search::Search;
+    use crate::select::Select;
+
+    const ENTRIES: &[&str] = &[
+        "firefox",
+        "foot",
+        "gimp",
+        "a name that is much too long to fit in the list, so it gets cut off at the edge",
+    ];
+
+    #[test]
+    fn launcher_at_startup() {
+        let mut scene = Scene::new(ENTRIES);
+        assert_snapshot("launcher_at_startup", &scene.render());
+    }
+
+    #[test]
+    fn launcher_filtering() {
+        let mut scene = Scene::new(ENTRIES);
+        scene.feed(typing("f"));
+        scene.feed(pressing_with(Key::LCtrl, Key::N));
+        assert_snapshot("launcher_filtering", &scene.render());
+    }
+
+    // A bug report comes with a recording (see recording.rs), which can be played back
+    // here to make sure the bug stays fixed.
+    #[test]
+    fn replaying_a_recording() {
+        let recording =
+            Recording::load(&manifest_dir().join("tests/fixtures/filtering.jsonl")).unwrap();
+        let mut scene = Scene::new(ENTRIES);
+        assert_eq!(recording.header, Header::new(scene.launcher.entry_names()));
+        scene.feed(recording.events());
+        // This is launcher_filtering again, typed by a person this time.
+        assert_snapshot("launcher_filtering", &scene.render());
+    }
+
+    // The match is in the part after the ellipsis, and it's still highlighted there.
+    #[test]
+    fn launcher_with_middle_ellipsis() {
+        let mut scene = Scene::new(ENTRIES);
+        scene.config.ellipsis = Ellipsis::Middle;
+        scene.feed(typing("edge"));
+        assert_snapshot("launcher_with_middle_ellipsis", &scene.render());
+    }
+
+    // Sans Mono doesn't have ☰ or ⚫, so they come from the fallback font, sitting on
+    // the same baseline as the rest.
+    #[test]
+    fn launcher_with_fallback_glyphs() {
+        let mut scene = Scene::new(&["☰ menu", "⚫ record", "plain"]);
+        scene.font = test_font_with_fallback();
+        assert_snapshot("launcher_with_fallback_glyphs", &scene.render());
+    }
+
+    // Hebrew and Arabic entries are drawn right-to-left, with the Arabic joined up,
+    // "(Kate)" and "Firefox" still the right way around in them, and the match
+    // highlighted wherever it ended up.
+    #[test]
+    fn launcher_with_bidi_entries() {
+        let mut scene = Scene::new(&[
+            "עורך טקסט (Kate)",
+            "متصفح Firefox",
+            "الآلة الحاسبة",
+            "מחשבון",
+        ]);
+        scene.font = test_font_with_fallback();
+        assert_snapshot("launcher_with_bidi_entries", &scene.render());
+
+        scene.feed(typing("حاسب"));
+        assert_snapshot("launcher_with_bidi_entries_filtered", &scene.render());
+    }
+
+    // Like on a screen with twice the DPI: the same layout, in twice the pixels.
+    #[test]
+    fn launcher_at_double_scale() {
+        let mut scene = Scene::new(ENTRIES);
+        scene.config.window.scale = Some(2.0);
+        scene.feed(typing("f"));
+        let picture = scene.render();
+        assert_eq!((picture.width(), picture.height()), (1600, 1000));
+        assert_snapshot("launcher_at_double_scale", &picture);
+    }
+
+    #[test]
+    fn launcher_scrolled_down() {
+        let names: Vec<String> = (0..30).map(|i| format!("entry {}", i)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let mut scene = Scene::new(&names);
+        scene.theme =
+            Theme::load(concat!(env!("CARGO_MANIFEST_DIR"), "/src/themes/dark.toml")).unwrap();
+        for _ in 0..12 {
+            scene.feed(pressing(Key::Down));
+        }
+        assert_snapshot("launcher_scrolled_down", &scene.render());
+    }
+
+    // Something that won't start says so where the prompt is, until the next key.
+    #[test]
+    fn launcher_showing_an_error() {
+        let mut scene = Scene::new(&[]);
+        let broken: EntryCallback = Box::new(|| {
+            Err(crate::error::Error::Launch(
+                "firefox".to_string(),
+                std::io::Error::from(std::io::ErrorKind::NotFound),
+            ))
+        });
+        let history = History::load(scene.config.mode, scene.config.history_size);
+        scene.launcher = ApplicationLauncher::with_entries(
+            &scene.config,
+            None,
+            vec![("firefox".to_string(), broken)],
+            history,
+        );
+        scene.feed(pressing(Key::Return));
+        assert_snapshot("launcher_showing_an_error", &scene.render());
+
+        // After that, it's as if nothing went wrong.
+        scene.feed(typing("f"));
+        let mut fine = Scene::new(&["firefox"]);
+        fine.feed(typing("f"));
+        assert!(scene.render() == fine.render());
+    }
+
+    // A 2x2 image blown up over most of the picture, half see-through at the bottom
+    // right, so it shows both the stretching and the blending.
+    #[test]
+    fn image_stretched_over_a_rect() {
+        let image = Image {
+            width: 2,
+            height: 2,
+            // Red and green on top, blue and half see-through white below.
+            pixels: vec![
+                255, 0, 0, 255, 0, 255, 0, 255, //
+                0, 0, 255, 255, 255, 255, 255, 128,
+            ],
+        };
+        let mut renderer =
+            SoftwareRenderer::new(64, 64, 1.0, test_font()).expect("The picture has a size");
+        renderer.clear(Theme::default().window.background.0);
+        renderer.image([8.0, 8.0, 48.0, 48.0], &image);
+        assert_snapshot("image_stretched_over_a_rect", renderer.pixmap());
+    }
+
+    #[test]
+    fn search_in_vi_normal_mode() {
+        let config = test_config();
+        let mut search = Search::new(ViMode::Insert, &config.word_chars);
+        search.arrange([10.0, 10.0, 380.0, 44.0], &config, &test_font());
+        feed(&mut search, typing("hello world"));
+        feed(&mut search, pressing(Key::Escape));
+        feed(&mut search, typing("b"));
+        assert_snapshot(
+            "search_in_vi_normal_mode",
+            &render_widget(&search, &config, &Theme::default(), [400, 64]),
+        );
+    }
+
+    // A query too long for the box scrolls so the cursor stays at the end of it, and
+    // moving the cursor back doesn't scroll it until the cursor gets to the start.
+    #[test]
+    fn search_scrolled_to_the_cursor() {
+        let config = test_config();
+        let mut search = Search::new(ViMode::Off, &config.word_chars);
+        search.arrange([10.0, 10.0, 380.0, 44.0], &config, &test_font());
+        feed(
+            &mut search,
+            typing("a query that is too long to fit in the box"),
+        );
+        let at_end = render_widget(&search, &config, &Theme::default(), [400, 64]);
+        assert_snapshot("search_scrolled_to_the_cursor", &at_end);
+
+        feed(&mut search, pressing(Key::Left));
+        let moved_back = render_widget(&search, &config, &Theme::default(), [400, 64]);
+        assert!(moved_back != at_end);
+        feed(&mut search, pressing(Key::Right));
+        let moved_again = render_widget(&search, &config, &Theme::default(), [400, 64]);
+        assert!(moved_again == at_end);
+    }
+
+    #[test]
+    fn select_with_hover() {
+        let config = test_config();
+        let mut select = Select::new(entries(ENTRIES));
+        select.configure(&config);
+        select.arrange([10.0, 10.0, 380.0, 200.0], &config, &test_font());
+        feed(&mut select, moving_mouse_to([100.0, 60.0]));
+        assert_snapshot(
+            "select_with_hover",
+            &render_widget(&select, &config, &Theme::default(), [400, 220]),
+        );
+    }
+}
@@ -0,0 +1,561 @@
+/* SPDX-License-Identifier: Zlib */
+
+// A theme decides how every part of the window looks. Themes are TOML files where every
+// key is optional; anything a theme leaves out comes from the default theme below, which
+// is the look WMController has always had. So a theme that only wants a different
+// background can be as short as
+//
+//     [window]
+//     background = "#282c34"
+//
+// Themes are looked up by name, first in $XDG_CONFIG_HOME/wmcontroller/themes/<name>.toml
+// and then in the built-in themes, so you can override a built-in theme by giving yours
+// the same name. A "name" with a slash in it is treated as a path to a theme file.
+//
+// Two theme names are special: "xresources" and "pywal" build a theme out of the
+// colors in your Xresources or pywal's cache (see palette.rs).
+
+use crate::configuration::{Color, Colors, Config, ConfigError};
+use crate::palette::Palette;
+use log::warn;
+use serde::{de, Deserialize, Deserializer};
+use std::path::{Path, PathBuf};
+
+// These are the colors of the default theme. Everything else is derived from them.
+const BACKGROUND_COLOR: Color = Color([18.0 / 255.0, 69.0 / 255.0, 81.0 / 255.0, 1.0]);
+const FOREGROUND_COLOR: Color = Color([1.0, 1.0, 1.0, 1.0]);
+const URGENT_COLOR: Color = Color([1.0, 85.0 / 255.0, 85.0 / 255.0, 1.0]);
+const TRANSPARENT: Color = Color([0.0, 0.0, 0.0, 0.0]);
+const HOVER_COLOR: Color = Color([1.0, 1.0, 1.0, 0.15]);
+
+// The themes that are compiled into the binary. The default theme is empty since
+// it's just Theme::default().
+pub const BUILTIN_THEMES: &[(&str, &str)] = &[
+    ("default", ""),
+    ("dark", include_str!("themes/dark.toml")),
+    ("light", include_str!("themes/light.toml")),
+    ("gruvbox", include_str!("themes/gruvbox.toml")),
+];
+
+// Themes that are made from a palette instead of a file.
+pub const PALETTE_THEMES: &[&str] = &["pywal", "xresources"];
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub window: WindowStyle,
+    // The text above the search bar.
+    pub prompt: TextStyle,
+    pub search: BoxStyle,
+    // The box around the select list.
+    pub list: ListStyle,
+    pub rows: RowStyles,
+    // How the part of an entry that matches the search is marked.
+    pub highlight: HighlightStyle,
+    pub scrollbar: ScrollbarStyle,
+    // Things that went wrong, like a program that wouldn't start, which are shown
+    // where the prompt is.
+    pub error: ErrorStyle,
+}
+
+// The window itself. It's the same as a BoxStyle, except that it has no border by default.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowStyle {
+    pub background: Color,
+    pub border_color: Color,
+    #[serde(deserialize_with = "deserialize_length")]
+    pub border_width: f64,
+    #[serde(deserialize_with = "deserialize_length")]
+    pub border_radius: f64,
+}
+
+// Anything that's drawn as a (maybe rounded) rectangle with text inside of it.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BoxStyle {
+    pub background: Color,
+    // The color of the text inside the box (and the cursor, for the search bar).
+    pub foreground: Color,
+    pub border_color: Color,
+    #[serde(deserialize_with = "deserialize_length")]
+    pub border_width: f64,
+    #[serde(deserialize_with = "deserialize_length")]
+    pub border_radius: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TextStyle {
+    pub foreground: Color,
+}
+
+// Like a TextStyle, but red by default.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ErrorStyle {
+    pub foreground: Color,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListStyle {
+    pub background: Color,
+    pub border_color: Color,
+    #[serde(deserialize_with = "deserialize_length")]
+    pub border_width: f64,
+    #[serde(deserialize_with = "deserialize_length")]
+    pub border_radius: f64,
+    // The lines between the rows.
+    pub separator_color: Color,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RowStyles {
+    // Each kind of row has its own default, so a row that's only partly in the theme is
+    // filled in from its own default and not from some generic row.
+    #[serde(deserialize_with = "deserialize_normal_row")]
+    pub normal: RowStyle,
+    #[serde(deserialize_with = "deserialize_selected_row")]
+    pub selected: RowStyle,
+    // Entries that want attention (dmenu's --urgent).
+    #[serde(deserialize_with = "deserialize_urgent_row")]
+    pub urgent: RowStyle,
+    // Entries that are "on" in some way (dmenu's --active).
+    #[serde(deserialize_with = "deserialize_active_row")]
+    pub active: RowStyle,
+    // If this is set, every other normal row gets this background instead, which
+    // makes long lists easier to follow.
+    pub alternate_background: Option<Color>,
+    // Drawn over the row under the mouse, so it should be see-through. A fully
+    // transparent color turns hover highlighting off.
+    pub hover_background: Option<Color>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RowStyle {
+    pub foreground: Color,
+    pub background: Color,
+}
+
+// A RowStyle the way it's written in a theme, where either color can be left out.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PartialRowStyle {
+    foreground: Option<Color>,
+    background: Option<Color>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighlightStyle {
+    // The color of the matching text. If it isn't set, the matching text keeps the
+    // color of the row it's in.
+    pub foreground: Option<Color>,
+    pub underline: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScrollbarStyle {
+    // The part that shows where you are in the list.
+    pub handle: Color,
+    // The part behind the handle.
+    pub track: Color,
+    #[serde(deserialize_with = "deserialize_length")]
+    pub width: f64,
+}
+
+impl Default for WindowStyle {
+    fn default() -> Self {
+        WindowStyle {
+            background: BACKGROUND_COLOR,
+            border_color: TRANSPARENT,
+            border_width: 0.0,
+            border_radius: 0.0,
+        }
+    }
+}
+
+impl Default for BoxStyle {
+    fn default() -> Self {
+        BoxStyle {
+            background: TRANSPARENT,
+            foreground: FOREGROUND_COLOR,
+            border_color: FOREGROUND_COLOR,
+            border_width: 1.0,
+            border_radius: 0.0,
+        }
+    }
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            foreground: FOREGROUND_COLOR,
+        }
+    }
+}
+
+impl Default for ErrorStyle {
+    fn default() -> Self {
+        ErrorStyle {
+            foreground: URGENT_COLOR,
+        }
+    }
+}
+
+impl Default for ListStyle {
+    fn default() -> Self {
+        ListStyle {
+            background: TRANSPARENT,
+            border_color: FOREGROUND_COLOR,
+            border_width: 1.0,
+            border_radius: 0.0,
+            separator_color: FOREGROUND_COLOR,
+        }
+    }
+}
+
+impl Default for RowStyles {
+    fn default() -> Self {
+        RowStyles {
+            normal: RowStyle {
+                foreground: FOREGROUND_COLOR,
+                background: TRANSPARENT,
+            },
+            // We invert the colors of the selected row.
+            selected: RowStyle {
+                foreground: BACKGROUND_COLOR,
+                background: FOREGROUND_COLOR,
+            },
+            urgent: RowStyle {
+                foreground: URGENT_COLOR,
+                background: TRANSPARENT,
+            },
+            active: RowStyle {
+                foreground: BACKGROUND_COLOR,
+                background: Color([1.0, 1.0, 1.0, 0.6]),
+            },
+            alternate_background: None,
+            hover_background: Some(HOVER_COLOR),
+        }
+    }
+}
+
+impl Default for HighlightStyle {
+    fn default() -> Self {
+        HighlightStyle {
+            foreground: None,
+            underline: true,
+        }
+    }
+}
+
+impl Default for ScrollbarStyle {
+    fn default() -> Self {
+        ScrollbarStyle {
+            handle: FOREGROUND_COLOR,
+            track: TRANSPARENT,
+            width: 4.0,
+        }
+    }
+}
+
+impl Theme {
+    // Load the theme the config asks for. `resource_manager` is only called for the
+    // xresources theme, and should return the root window's RESOURCE_MANAGER property.
+    pub fn from_config<F>(config: &Config, resource_manager: F) -> Result<Self, ConfigError>
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        let theme = match config.theme.as_str() {
+            "xresources" => {
+                let palette = Palette::from_xresources(&resource_manager().unwrap_or_default());
+                Self::from_palette(&palette.or(&config.palette))
+            }
+            "pywal" => {
+                let palette = pywal_palette(Palette::pywal_path().as_deref());
+                Self::from_palette(&palette.or(&config.palette))
+            }
+            name => Self::load(name)?,
+        };
+        Ok(theme.with_colors(&config.colors))
+    }
+
+    // Put [colors] from the config where the default theme uses its background and
+    // foreground. The selected row has them the other way around.
+    pub fn with_colors(mut self, colors: &Colors) -> Self {
+        if let Some(background) = colors.background {
+            self.window.background = background;
+            self.rows.selected.foreground = background;
+        }
+        if let Some(foreground) = colors.foreground {
+            self.prompt.foreground = foreground;
+            self.search.foreground = foreground;
+            self.search.border_color = foreground;
+            self.list.border_color = foreground;
+            self.list.separator_color = foreground;
+            self.rows.normal.foreground = foreground;
+            self.rows.selected.background = foreground;
+            self.scrollbar.handle = foreground;
+        }
+        self
+    }
+
+    // Spread a palette over the theme, roughly the way terminal programs use it.
+    pub fn from_palette(palette: &Palette) -> Self {
+        let background = palette.get("background");
+        let foreground = palette.get("foreground");
+        // Bright black is the usual "dim" color, which is good for lines.
+        let dim = palette.get("color8");
+
+        Theme {
+            window: WindowStyle {
+                background,
+                ..WindowStyle::default()
+            },
+            prompt: TextStyle { foreground },
+            search: BoxStyle {
+                foreground,
+                border_color: dim,
+                ..BoxStyle::default()
+            },
+            list: ListStyle {
+                border_color: dim,
+                separator_color: dim,
+                ..ListStyle::default()
+            },
+            rows: RowStyles {
+                normal: RowStyle {
+                    foreground,
+                    background: TRANSPARENT,
+                },
+                selected: RowStyle {
+                    foreground: background,
+                    background: palette.get("color4"),
+                },
+                urgent: RowStyle {
+                    foreground: palette.get("color1"),
+                    background: TRANSPARENT,
+                },
+                active: RowStyle {
+                    foreground: palette.get("color2"),
+                    background: TRANSPARENT,
+                },
+                alternate_background: None,
+                hover_background: Some(HOVER_COLOR),
+            },
+            highlight: HighlightStyle {
+                foreground: Some(palette.get("color3")),
+                underline: false,
+            },
+            scrollbar: ScrollbarStyle {
+                handle: dim,
+                ..ScrollbarStyle::default()
+            },
+            error: ErrorStyle {
+                foreground: palette.get("color1"),
+            },
+        }
+    }
+
+    // The file the theme with this name is read from, if it's read from a file at all.
+    // The file doesn't have to exist; if it's created, it takes over from the built-in
+    // theme with the same name.
+    pub fn file_path(name: &str) -> Option<PathBuf> {
+        match name {
+            "xresources" => None,
+            "pywal" => Palette::pywal_path(),
+            name if name.contains('/') => Some(PathBuf::from(name)),
+            name => Self::themes_dir().map(|dir| dir.join(format!("{}.toml", name))),
+        }
+    }
+
+    pub fn themes_dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("wmcontroller").join("themes"))
+    }
+
+    // Find a theme by name (or path, see the top of this file) and load it.
+    pub fn load(name: &str) -> Result<Self, ConfigError> {
+        if name.contains('/') {
+            return Self::load_from(Path::new(name));
+        }
+
+        if let Some(path) = Self::themes_dir().map(|dir| dir.join(format!("{}.toml", name))) {
+            if path.exists() {
+                return Self::load_from(&path);
+            }
+        }
+
+        match BUILTIN_THEMES.iter().find(|(builtin, _)| *builtin == name) {
+            Some((_, contents)) => Ok(Self::parse(contents)
+                .unwrap_or_else(|e| panic!("Built-in theme {} is invalid: {}", name, e))),
+            None => Err(ConfigError::UnknownTheme(name.to_string())),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        Self::parse(&contents).map_err(|e| ConfigError::Parse(path.to_owned(), e))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    // The names of every theme we can find: the built-in ones and the ones in the
+    // themes directory, without duplicates.
+    pub fn list() -> Vec<String> {
+        let mut names: Vec<String> = BUILTIN_THEMES
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(PALETTE_THEMES.iter().copied())
+            .map(str::to_string)
+            .collect();
+
+        if let Some(Ok(dir_entries)) = Self::themes_dir().map(std::fs::read_dir) {
+            for path in dir_entries.flatten().map(|dir_entry| dir_entry.path()) {
+                if path.extension().is_some_and(|ext| ext == "toml") {
+                    if let Some(name) = path.file_stem() {
+                        names.push(name.to_string_lossy().into_owned());
+                    }
+                }
+            }
+        }
+
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+// Border widths and such can't be negative.
+fn deserialize_length<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = f64::deserialize(deserializer)?;
+    if value < 0.0 || !value.is_finite() {
+        return Err(de::Error::custom("value must be zero or greater"));
+    }
+    Ok(value)
+}
+
+// Read a row's style, taking whatever it leaves out from `default`.
+fn deserialize_row<'de, D>(deserializer: D, default: RowStyle) -> Result<RowStyle, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let row = PartialRowStyle::deserialize(deserializer)?;
+    Ok(RowStyle {
+        foreground: row.foreground.unwrap_or(default.foreground),
+        background: row.background.unwrap_or(default.background),
+    })
+}
+
+fn deserialize_normal_row<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<RowStyle, D::Error> {
+    deserialize_row(deserializer, RowStyles::default().normal)
+}
+
+fn deserialize_selected_row<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<RowStyle, D::Error> {
+    deserialize_row(deserializer, RowStyles::default().selected)
+}
+
+fn deserialize_urgent_row<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<RowStyle, D::Error> {
+    deserialize_row(deserializer, RowStyles::default().urgent)
+}
+
+fn deserialize_active_row<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<RowStyle, D::Error> {
+    deserialize_row(deserializer, RowStyles::default().active)
+}
+
+// pywal's colors, or nothing if there aren't any, like before pywal has ever run. Like
+// with Xresources, whatever's missing comes from the config's palette then.
+fn pywal_palette(path: Option<&Path>) -> Palette {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            warn!("Failed to find pywal's colors, there's no cache directory");
+            return Palette::default();
+        }
+    };
+    Palette::from_pywal(path).unwrap_or_else(|e| {
+        warn!("Using the palette from the config instead: {}", e);
+        Palette::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_themes_parse() {
+        for (name, contents) in BUILTIN_THEMES {
+            if let Err(e) = Theme::parse(contents) {
+                panic!("theme {} doesn't parse: {}", name, e);
+            }
+        }
+    }
+
+    #[test]
+    fn missing_keys_come_from_the_default_theme() {
+        let theme = Theme::parse("[window]\nbackground = \"#000000\"\n").unwrap();
+        assert_eq!(theme.window.background, Color([0.0, 0.0, 0.0, 1.0]));
+        assert_eq!(theme.window.border_width, 0.0);
+        assert_eq!(theme.rows, Theme::default().rows);
+    }
+
+    #[test]
+    fn missing_row_colors_come_from_the_same_row_in_the_default_theme() {
+        let theme = Theme::parse("[rows.selected]\nforeground = \"#000000\"\n").unwrap();
+        assert_eq!(theme.rows.selected.foreground, Color([0.0, 0.0, 0.0, 1.0]));
+        assert_eq!(
+            theme.rows.selected.background,
+            Theme::default().rows.selected.background
+        );
+        assert_eq!(theme.rows.urgent, Theme::default().rows.urgent);
+    }
+
+    #[test]
+    fn config_colors_replace_the_themes() {
+        let config = Config::parse(
+            "theme = \"gruvbox\"\n[colors]\nbackground = \"#000000\"\nforeground = \"#ff0000\"\n",
+        )
+        .unwrap();
+        let theme = Theme::from_config(&config, || None).unwrap();
+        let (black, red) = (Color([0.0, 0.0, 0.0, 1.0]), Color([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(theme.window.background, black);
+        assert_eq!(theme.rows.normal.foreground, red);
+        assert_eq!(
+            theme.rows.selected,
+            RowStyle {
+                foreground: black,
+                background: red
+            }
+        );
+        // Everything else is still gruvbox.
+        assert_eq!(
+            theme.rows.urgent,
+            Theme::load("gruvbox").unwrap().rows.urgent
+        );
+    }
+
+    #[test]
+    fn missing_pywal_colors_fall_back_to_the_config() {
+        assert_eq!(pywal_palette(None), Palette::default());
+        let nowhere = Path::new("/nonexistent/wal/colors.json");
+        assert_eq!(pywal_palette(Some(nowhere)), Palette::default());
+    }
+}
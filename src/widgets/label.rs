@@ -0,0 +1,84 @@
+/* SPDX-License-Identifier: Zlib */
+
+use crate::configuration::Config;
+use crate::input::Action;
+use crate::render::{Font, Renderer};
+use crate::theme::Theme;
+use crate::widgets::Widget;
+use piston_window::*;
+
+// A line of text, like the prompt. It doesn't do anything but sit there.
+pub struct Label {
+    text: String,
+    // The font size.
+    size: u32,
+    // Drawn in the theme's error color instead of the prompt's.
+    is_error: bool,
+    bounds: [f64; 4],
+}
+
+impl Label {
+    pub fn new(text: &str, size: u32) -> Self {
+        Label {
+            text: text.to_string(),
+            size,
+            is_error: false,
+            bounds: [0.0; 4],
+        }
+    }
+
+    // A label for saying what went wrong, which starts out empty.
+    pub fn error(size: u32) -> Self {
+        Label {
+            is_error: true,
+            ..Self::new("", size)
+        }
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+    }
+
+    pub fn set_size(&mut self, size: u32) {
+        self.size = size;
+    }
+}
+
+impl Widget for Label {
+    // As wide as the text and as tall as the font size. Anything that hangs below
+    // the baseline is the container's problem.
+    fn measure(&self, available: [f64; 2], _config: &Config, font: &Font) -> [f64; 2] {
+        let width = font.text_width(self.size, &self.text);
+        [
+            width.min(available[0]),
+            f64::from(self.size).min(available[1]),
+        ]
+    }
+
+    fn arrange(&mut self, rect: [f64; 4], _config: &Config, _font: &Font) {
+        self.bounds = rect;
+    }
+
+    fn bounds(&self) -> [f64; 4] {
+        self.bounds
+    }
+
+    fn draw<R: Renderer>(&self, _config: &Config, theme: &Theme, renderer: &mut R) {
+        if self.text.is_empty() {
+            return;
+        }
+        // The text sits on the bottom of the label, so labels of different sizes next
+        // to each other line up.
+        let baseline = self.bounds[1] + self.bounds[3];
+        let color = if self.is_error {
+            theme.error.foreground
+        } else {
+            theme.prompt.foreground
+        };
+        renderer.text(&self.text, color.0, self.size, [self.bounds[0], baseline]);
+    }
+
+    fn handle_event(&mut self, _ev: &Event) {}
+
+    fn handle_action(&mut self, _action: Action) {}
+}
@@ -1,11 +1,30 @@
+pub mod label;
 pub mod search;
 pub mod select;
 
-use piston_window::*;
+use crate::configuration::Config;
+use crate::input::Action;
+use crate::render::{Font, Renderer};
+use crate::theme::Theme;
+use piston_window::Event;
 
 pub trait Widget {
-    fn draw<G>(&self, coords: [f64; 2], c: &Context, g: &mut G, glyph_cache: &mut Glyphs)
-    where
-        G: Graphics<Texture = Texture<gfx_device_gl::Resources>>;
+    // How big (width, height) the widget would like to be, if it can have up to
+    // `available`. Containers use this to decide how to split up their space.
+    fn measure(&self, available: [f64; 2], config: &Config, font: &Font) -> [f64; 2];
+    // Tell the widget the rectangle (x, y, width, height) it gets, which is where it
+    // draws and where it takes mouse events from. This happens before every event, so
+    // a widget can count on its bounds matching the window (see layout.rs).
+    fn arrange(&mut self, rect: [f64; 4], config: &Config, font: &Font);
+    fn bounds(&self) -> [f64; 4];
+    // The config and theme are passed in on every draw instead of being stored in the
+    // widget, since we want to be able to change details about the drawing independently
+    // of the state that is stored in the widget.
+    // Widgets don't know what they're drawing with, just that it's a Renderer (see
+    // render/mod.rs).
+    fn draw<R: Renderer>(&self, config: &Config, theme: &Theme, renderer: &mut R);
     fn handle_event(&mut self, ev: &Event);
+    // Keys go through the keybindings (see input.rs) and arrive here as actions.
+    // handle_event still gets everything else, like typed text.
+    fn handle_action(&mut self, action: Action);
 }
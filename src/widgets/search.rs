@@ -1,58 +1,512 @@
 /* SPDX-License-Identifier: Zlib */
 
-// Technically I can just import FOREGROUND_COLOR from main.rs (root of crate), but I feel better about this.
-use crate::configuration::FOREGROUND_COLOR;
-use core::iter::FromIterator;
+use crate::configuration::{Config, ViMode};
+use crate::input::Action;
+use crate::layout::{search_height, TEXT_INSET};
+use crate::render::{Font, Renderer};
+use crate::theme::Theme;
 use piston_window::*;
+use std::cell::Cell;
+use std::ops::Range;
 
 use crate::widgets::Widget;
 use log::debug;
+use unicode_segmentation::UnicodeSegmentation;
 
 pub struct Search {
     pub buffer: String,
-    // We are going to implement modal editing in the application launcher
+    // Where the cursor is in the buffer, as a byte index. It's always on a char boundary,
+    // so it can be used to slice the buffer directly, and everything that moves it keeps
+    // it between grapheme clusters (what you'd think of as one character, like an emoji
+    // with a skin tone or a letter with a combining accent).
+    cursor: usize,
+    // Whether we have vi-style modes at all. Without them we're always in insert mode.
+    modal: bool,
+    // In insert mode, typing goes into the buffer. Otherwise we're in vi's normal mode,
+    // where typing runs commands (see normal_mode_command).
     pub insert_mode: bool,
+    // The d or c of a dw/cw/dd that's waiting for its second key.
+    pending_operator: Option<char>,
+    // Characters that count as part of a word for Alt+B/Alt+F and Ctrl+Backspace, on
+    // top of letters and digits (see is_word).
+    word_chars: String,
     // We want to display the placeholder "Search" text until the first keypress,
     // so we have this boolean to check whether or not to replace that placeholder.
     events_run: bool,
-    ctrl_pressed: bool,
+    // Searches from earlier runs, oldest first (see history.rs).
+    history: Vec<String>,
+    // Which of them Alt+P/Alt+N are on. None is the line being typed.
+    history_index: Option<usize>,
+    // What was being typed before Alt+P, so Alt+N can go back to it.
+    draft: String,
+    // Set while Ctrl+R is searching the history.
+    history_search: Option<HistorySearch>,
+    // How far the text is scrolled to the left, when it's too long for the box. Like
+    // the list's scroll_offset, it only moves when the cursor would go out of sight, so
+    // it's worked out in draw from where it was before.
+    scroll: Cell<f64>,
+    bounds: [f64; 4],
 }
+
+// The state of a Ctrl+R search. The buffer always shows the match (if there is one),
+// so the list filters as you search.
+struct HistorySearch {
+    query: String,
+    // The index in the history of the entry we found.
+    found: Option<usize>,
+    // Set when the query isn't in the history anywhere (before `found`).
+    failing: bool,
+    // The buffer from before the search, for when it's cancelled.
+    original: String,
+}
+
 impl Search {
-    pub fn new() -> Self {
+    pub fn new(vi_mode: ViMode, word_chars: &str) -> Self {
         Search {
             buffer: "".to_string(),
-            insert_mode: false,
+            cursor: 0,
+            modal: vi_mode != ViMode::Off,
+            insert_mode: vi_mode != ViMode::Normal,
+            pending_operator: None,
+            word_chars: word_chars.to_string(),
             events_run: false,
-            ctrl_pressed: false,
+            history: Vec::new(),
+            history_index: None,
+            draft: String::new(),
+            history_search: None,
+            scroll: Cell::new(0.0),
+            bounds: [0.0; 4],
+        }
+    }
+
+    // For when the config is reloaded. Turning vi mode on or off starts in the mode the
+    // config asks for, but only changing which mode that is leaves the current one be.
+    pub fn set_vi_mode(&mut self, vi_mode: ViMode) {
+        let modal = vi_mode != ViMode::Off;
+        if modal != self.modal {
+            self.modal = modal;
+            self.insert_mode = vi_mode != ViMode::Normal;
+            self.pending_operator = None;
+        }
+    }
+
+    pub fn set_word_chars(&mut self, word_chars: &str) {
+        self.word_chars = word_chars.to_string();
+    }
+
+    // Replace whatever is in the search bar, as if the user had typed `text`.
+    pub fn set_buffer(&mut self, text: &str) {
+        self.buffer = text.to_string();
+        self.cursor = self.buffer.len();
+        self.events_run = !self.buffer.is_empty();
+    }
+
+    pub fn set_history(&mut self, history: Vec<String>) {
+        self.history = history;
+        self.history_index = None;
+    }
+
+    pub fn searching_history(&self) -> bool {
+        self.history_search.is_some()
+    }
+
+    // Show an entry from the history, with the cursor at the end like readline does.
+    fn show_history_entry(&mut self, index: usize) {
+        self.history_index = Some(index);
+        self.set_buffer(&self.history[index].clone());
+    }
+
+    // Alt+P: the search before the one we're showing.
+    fn history_previous(&mut self) {
+        let previous = match self.history_index {
+            None if !self.history.is_empty() => {
+                self.draft = self.buffer.clone();
+                self.history.len() - 1
+            }
+            Some(index) if index > 0 => index - 1,
+            _ => return,
+        };
+        self.show_history_entry(previous);
+    }
+
+    // Alt+N: the search after it, and then whatever was being typed.
+    fn history_next(&mut self) {
+        match self.history_index {
+            Some(index) if index + 1 < self.history.len() => self.show_history_entry(index + 1),
+            Some(_) => {
+                let draft = std::mem::take(&mut self.draft);
+                self.set_buffer(&draft);
+                self.history_index = None;
+            }
+            None => {}
+        }
+    }
+
+    // Ctrl+R starts a search, and pressing it again finds the next older match.
+    fn search_history(&mut self) {
+        match self.history_search {
+            Some(ref search) => {
+                let before = search.found.unwrap_or(self.history.len());
+                self.find_in_history(before);
+            }
+            None => {
+                self.history_search = Some(HistorySearch {
+                    query: String::new(),
+                    found: None,
+                    failing: false,
+                    original: self.buffer.clone(),
+                });
+            }
+        }
+    }
+
+    // Find the newest entry older than `before` with the query in it and show it.
+    // If nothing matches, the last match stays up, like in readline.
+    fn find_in_history(&mut self, before: usize) {
+        let query = match self.history_search {
+            Some(ref search) => search.query.clone(),
+            None => return,
+        };
+        let found = self.history[..before.min(self.history.len())]
+            .iter()
+            .rposition(|entry| entry.contains(&query));
+        if let Some(index) = found {
+            self.buffer = self.history[index].clone();
+            self.cursor = self.buffer.find(&query).unwrap_or(0);
+            self.events_run = true;
+        }
+        if let Some(ref mut search) = self.history_search {
+            search.found = found.or(search.found);
+            search.failing = found.is_none();
+        }
+    }
+
+    // Keep whatever the search found and go back to editing.
+    pub fn finish_history_search(&mut self) {
+        if let Some(search) = self.history_search.take() {
+            self.history_index = search.found;
+            self.events_run = !self.buffer.is_empty();
+        }
+    }
+
+    // Escape: put back what was there before the search.
+    fn cancel_history_search(&mut self) {
+        if let Some(search) = self.history_search.take() {
+            self.set_buffer(&search.original);
+        }
+    }
+
+    // The label shown in front of the search bar during a Ctrl+R search.
+    fn history_search_label(&self) -> Option<String> {
+        self.history_search.as_ref().map(|search| {
+            let failing = if search.failing { "failing " } else { "" };
+            format!("({}reverse-i-search)`{}': ", failing, search.query)
+        })
+    }
+
+    // Insert pasted text at the cursor. The search bar is one line, so line breaks are
+    // dropped.
+    pub fn insert(&mut self, text: &str) {
+        let text: String = text
+            .chars()
+            .filter(|&ch| ch != '\n' && ch != '\r')
+            .collect();
+        if text.is_empty() {
+            return;
+        }
+        self.buffer.insert_str(self.cursor, &text);
+        self.cursor += text.len();
+        self.events_run = true;
+    }
+
+    // The byte index of the grapheme cluster before the cursor, if there is one.
+    fn previous_grapheme_boundary(&self) -> Option<usize> {
+        self.buffer[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map(|(i, _)| i)
+    }
+
+    // The byte index right after the grapheme cluster at the cursor, if there is one.
+    fn next_grapheme_boundary(&self) -> Option<usize> {
+        self.buffer[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map(|grapheme| self.cursor + grapheme.len())
+    }
+
+    // Whether a segment from split_word_bounds is part of a word, and not the spaces or
+    // punctuation between words. The Unicode word boundary rules already keep things
+    // like "can't" and "3.14" together. Segments made of nothing but word_chars count
+    // too, and since a word is a run of these segments, with "-" in word_chars
+    // "foo-bar" is one word.
+    fn is_word(&self, segment: &str) -> bool {
+        segment.chars().any(char::is_alphanumeric)
+            || (!segment.is_empty() && segment.chars().all(|ch| self.word_chars.contains(ch)))
+    }
+
+    // Where Alt+B and Ctrl+Backspace go: the start of the word before the cursor,
+    // skipping anything between it and the cursor that isn't a word, like readline.
+    fn previous_word_start(&self) -> usize {
+        let mut word_start = None;
+        for (i, segment) in self.buffer[..self.cursor].split_word_bound_indices().rev() {
+            if self.is_word(segment) {
+                word_start = Some(i);
+            } else if word_start.is_some() {
+                break;
+            }
+        }
+        word_start.unwrap_or(0)
+    }
+
+    // Where Alt+F goes: the end of the word after the cursor.
+    fn next_word_end(&self) -> usize {
+        let mut word_end = None;
+        for (i, segment) in self.buffer[self.cursor..].split_word_bound_indices() {
+            if self.is_word(segment) {
+                word_end = Some(self.cursor + i + segment.len());
+            } else if word_end.is_some() {
+                break;
+            }
         }
+        word_end.unwrap_or(self.buffer.len())
+    }
+
+    // Remove the text between the two byte indices and put the cursor where it was.
+    fn delete_range(&mut self, start: usize, end: usize) {
+        self.buffer.replace_range(start..end, "");
+        self.cursor = start;
+    }
+
+    // What to show next to the prompt so you know which mode you're in. Nothing if
+    // vi mode is off, since then there's only one mode.
+    pub fn mode_name(&self) -> Option<&'static str> {
+        match (self.modal, self.insert_mode) {
+            (false, _) => None,
+            (true, true) => Some("-- INSERT --"),
+            (true, false) => Some("-- NORMAL --"),
+        }
+    }
+
+    // Whether an Escape press is ours (to leave insert mode or cancel a d/c) instead
+    // of closing the window.
+    pub fn captures_escape(&self) -> bool {
+        self.modal && (self.insert_mode || self.pending_operator.is_some())
+    }
+
+    // The first char of each grapheme cluster in the buffer with their byte indices,
+    // and which of them the cursor is on (the length of the list if it's at the very
+    // end). The first char is all vi_char_class needs to know what kind of character
+    // the cluster is.
+    fn chars_around_cursor(&self) -> (Vec<(usize, char)>, usize) {
+        let chars: Vec<(usize, char)> = self
+            .buffer
+            .grapheme_indices(true)
+            .filter_map(|(i, grapheme)| Some((i, grapheme.chars().next()?)))
+            .collect();
+        let cursor_char = chars
+            .iter()
+            .position(|&(i, _)| i == self.cursor)
+            .unwrap_or(chars.len());
+        (chars, cursor_char)
+    }
+
+    // Turn an index into the list from chars_around_cursor back into a byte index.
+    fn byte_index(&self, chars: &[(usize, char)], char_index: usize) -> usize {
+        chars.get(char_index).map_or(self.buffer.len(), |&(i, _)| i)
+    }
+
+    // Where vi's w goes: the start of the next word. Unlike Alt+F, vi counts a run of
+    // punctuation as a word of its own.
+    fn vi_next_word_start(&self) -> usize {
+        let (chars, mut i) = self.chars_around_cursor();
+        if let Some(&(_, ch)) = chars.get(i) {
+            let class = vi_char_class(ch);
+            if class != CharClass::Blank {
+                while i < chars.len() && vi_char_class(chars[i].1) == class {
+                    i += 1;
+                }
+            }
+        }
+        while i < chars.len() && vi_char_class(chars[i].1) == CharClass::Blank {
+            i += 1;
+        }
+        self.byte_index(&chars, i)
+    }
+
+    // Where vi's b goes: the start of this word, or the previous one if we're already
+    // at the start.
+    fn vi_previous_word_start(&self) -> usize {
+        let (chars, cursor_char) = self.chars_around_cursor();
+        if cursor_char == 0 {
+            return 0;
+        }
+        let mut i = cursor_char - 1;
+        while i > 0 && vi_char_class(chars[i].1) == CharClass::Blank {
+            i -= 1;
+        }
+        let class = vi_char_class(chars[i].1);
+        while i > 0 && vi_char_class(chars[i - 1].1) == class {
+            i -= 1;
+        }
+        self.byte_index(&chars, i)
+    }
+
+    // Where vi's e goes: the last char of this word, or the next one if we're already
+    // on the last char.
+    fn vi_word_end(&self) -> usize {
+        let (chars, cursor_char) = self.chars_around_cursor();
+        let mut i = cursor_char + 1;
+        while i < chars.len() && vi_char_class(chars[i].1) == CharClass::Blank {
+            i += 1;
+        }
+        if i >= chars.len() {
+            return self.cursor;
+        }
+        let class = vi_char_class(chars[i].1);
+        while i + 1 < chars.len() && vi_char_class(chars[i + 1].1) == class {
+            i += 1;
+        }
+        self.byte_index(&chars, i)
+    }
+
+    // What cw changes. Vim special-cases it to stop at the end of the word instead of
+    // eating the whitespace after it like dw does.
+    fn vi_change_word_end(&self) -> usize {
+        let (chars, mut i) = self.chars_around_cursor();
+        match chars.get(i) {
+            Some(&(_, ch)) if vi_char_class(ch) != CharClass::Blank => {
+                let class = vi_char_class(ch);
+                while i < chars.len() && vi_char_class(chars[i].1) == class {
+                    i += 1;
+                }
+                self.byte_index(&chars, i)
+            }
+            _ => self.vi_next_word_start(),
+        }
+    }
+
+    fn enter_insert_mode(&mut self, cursor: usize) {
+        self.cursor = cursor;
+        self.insert_mode = true;
+    }
+
+    // Run one normal mode command. These come from Input::Text rather than key presses
+    // so that things like $ and A work no matter the keyboard layout.
+    fn normal_mode_command(&mut self, command: char) {
+        if let Some(operator) = self.pending_operator.take() {
+            match (operator, command) {
+                ('d', 'd') => self.delete_range(0, self.buffer.len()),
+                ('d', 'w') => self.delete_range(self.cursor, self.vi_next_word_start()),
+                ('c', 'w') => {
+                    self.delete_range(self.cursor, self.vi_change_word_end());
+                    self.insert_mode = true;
+                }
+                // Like in vim, anything else just cancels the operator.
+                _ => {}
+            }
+        } else {
+            match command {
+                'h' => self.cursor = self.previous_grapheme_boundary().unwrap_or(self.cursor),
+                'l' => self.cursor = self.next_grapheme_boundary().unwrap_or(self.cursor),
+                'w' => self.cursor = self.vi_next_word_start(),
+                'b' => self.cursor = self.vi_previous_word_start(),
+                'e' => self.cursor = self.vi_word_end(),
+                '0' => self.cursor = 0,
+                '$' => self.cursor = self.buffer.len(),
+                'x' => {
+                    if let Some(next) = self.next_grapheme_boundary() {
+                        self.delete_range(self.cursor, next);
+                    }
+                }
+                'd' | 'c' => self.pending_operator = Some(command),
+                'i' => self.enter_insert_mode(self.cursor),
+                'a' => self.enter_insert_mode(self.next_grapheme_boundary().unwrap_or(self.cursor)),
+                'I' => self.enter_insert_mode(0),
+                // / is where you'd start searching in vim, but wiping out what you've
+                // typed so far is easy to do by accident, so it's the same as A. dd is
+                // there for starting over.
+                'A' | '/' => self.enter_insert_mode(self.buffer.len()),
+                // j and k move through the list, which is the launcher's business.
+                _ => {}
+            }
+        }
+
+        // In normal mode the cursor is on a char, not between two of them, so it can't
+        // be past the last one.
+        if !self.insert_mode && self.cursor == self.buffer.len() {
+            self.cursor = self.previous_grapheme_boundary().unwrap_or(0);
+        }
+        if self.buffer.is_empty() {
+            self.events_run = false;
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+// Vi's idea of a word: letters, digits and underscores, or a run of other punctuation.
+fn vi_char_class(ch: char) -> CharClass {
+    if ch.is_whitespace() {
+        CharClass::Blank
+    } else if ch.is_alphanumeric() || ch == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
     }
 }
+
+// How far to scroll text that's `text_width` wide so `cursor` (where the cursor is in
+// it, from its start) fits in `room`, moving it as little as possible from `scroll`. It
+// doesn't scroll further than it has to to show the end of the text, so there isn't a
+// gap after it when you delete from the end.
+fn keep_in_view(scroll: f64, cursor: Range<f64>, text_width: f64, room: f64) -> f64 {
+    let mut scroll = scroll;
+    if cursor.end - scroll > room {
+        scroll = cursor.end - room;
+    }
+    if cursor.start < scroll {
+        scroll = cursor.start;
+    }
+    scroll.min(text_width.max(cursor.end) - room).max(0.0)
+}
+
 impl Widget for Search {
     // Function to draw the search bar on the screen
-    fn draw<G>(&self, coords: [f64; 2], c: &Context, g: &mut G, glyph_cache: &mut Glyphs)
-    where
-        G: Graphics<Texture = Texture<gfx_device_gl::Resources>>,
-    {
-        const RECT_HEIGHT: f64 = 40.0;
-
-        // TODO we want to actually be able to set the width of the text box rectangle on the fly
-        // or as an argument to this draw function (we wouldn't want it to be a struct param)
-        // since we want people to be able to change details about the drawing independently
-        // of the state that is stored in this struct.
-        Rectangle::new_border(FOREGROUND_COLOR, 1.0)
-            .color([0.0, 0.0, 0.0, 0.0])
-            .draw(
-                // TODO calculate RECT_WIDTH (700.0) from the window size.
-                [coords[0], coords[1], 700.0, RECT_HEIGHT],
-                &Default::default(),
-                c.transform,
-                g,
-            );
+    // As wide as it's allowed to be, and tall enough for the font.
+    fn measure(&self, available: [f64; 2], config: &Config, _font: &Font) -> [f64; 2] {
+        [available[0], search_height(config).min(available[1])]
+    }
+
+    fn arrange(&mut self, rect: [f64; 4], _config: &Config, _font: &Font) {
+        self.bounds = rect;
+    }
+
+    fn bounds(&self) -> [f64; 4] {
+        self.bounds
+    }
+
+    fn draw<R: Renderer>(&self, config: &Config, theme: &Theme, renderer: &mut R) {
+        let rect = self.bounds;
+        let foreground_color = theme.search.foreground.0;
+
+        renderer.rectangle(
+            rect,
+            theme.search.background.0,
+            Some((theme.search.border_color.0, theme.search.border_width)),
+            theme.search.border_radius,
+        );
 
-        const SEARCH_FONTSIZE: f64 = RECT_HEIGHT / 2.0;
+        let search_fontsize = config.font.size;
 
         // The text to display/use to calculate cursor position
-        let render_text = if self.events_run {
+        let searching_history = self.history_search.is_some();
+        let render_text = if self.events_run || searching_history {
             &self.buffer
         } else {
             "Search"
@@ -62,136 +516,591 @@ impl Widget for Search {
         // made the text not look vertically cenetered in a rectangle, so we
         // want a slightly shorter character. We can use that to find the
         // "max" (ish) height that our text will be… although this is still a hack.
-        let char_height = glyph_cache
-            .character(SEARCH_FONTSIZE as u32, 'A')
-            .expect("Failed to get max char height to vertically center text in the window!")
-            .top();
+        let char_height = renderer.font().glyph_top(search_fontsize, 'A');
 
-        let text_xpos = coords[0] + 15.0;
+        let mut text_xpos = rect[0] + TEXT_INSET;
         // We use our character height from before to calculate where to put our text.
         // The point we need is actually the bottom left of the text, so what we can do is
-        let text_ypos = coords[1] + (RECT_HEIGHT / 2.0) + (char_height / 2.0);
-
-        text::Text::new_color(FOREGROUND_COLOR, SEARCH_FONTSIZE as u32 * 2)
-            .draw(
-                render_text,
-                glyph_cache,
-                &DrawState::default(),
-                c.transform.trans(text_xpos, text_ypos).zoom(0.5),
-                g,
-            )
-            .unwrap();
-
-        // Loop through all the characters to find the width of all the text.
-        // This is for determining cursor height.
-        let mut cursor_offset = 0.0;
-        for ch in render_text.chars() {
-            let ch = glyph_cache
-                .character(SEARCH_FONTSIZE as u32, ch)
-                .expect("Failed to get size of character to display the cursor!");
-            cursor_offset += ch.advance_width();
-        }
-
-        // Calculate the width of the text we're rendering so we know where to put the cursor.
-        // We don't render the cursor until we start populating the buffer
-        if self.events_run {
-            line(
-                FOREGROUND_COLOR,
-                1.0,
+        let text_ypos = rect[1] + (rect[3] / 2.0) + (char_height / 2.0);
+
+        // During a Ctrl+R search, the label goes in front of the text, which moves
+        // everything after it over. It doesn't scroll with the text.
+        if let Some(label) = self.history_search_label() {
+            renderer.push_clip(rect);
+            renderer.text(
+                &label,
+                theme.prompt.foreground.0,
+                search_fontsize,
+                [text_xpos, text_ypos],
+            );
+            renderer.pop_clip();
+            text_xpos += renderer.font().text_width(search_fontsize, &label);
+        }
+
+        // Find where the cursor goes from how the text is laid out, kerning, right-to-left
+        // text and all.
+        let (placed, text_width) = renderer.font().layout(search_fontsize, render_text);
+        let caret = Font::caret_x(&placed, self.cursor);
+        // We don't render the cursor until we start populating the buffer. In normal
+        // mode the cursor is a box around the grapheme it's on, like in vim, and
+        // otherwise it's a line.
+        let show_cursor = self.events_run || searching_history;
+        let box_cursor = show_cursor && !self.insert_mode && !searching_history;
+        let (cursor_offset, cursor_width) = if box_cursor {
+            let grapheme_end = self.next_grapheme_boundary().unwrap_or(self.cursor);
+            let grapheme = placed
+                .iter()
+                .filter(|place| (self.cursor..grapheme_end).contains(&place.index));
+            let left = grapheme
+                .clone()
+                .map(|place| place.x)
+                .fold(f64::INFINITY, f64::min);
+            let right = grapheme
+                .map(|place| place.x + place.width)
+                .fold(f64::NEG_INFINITY, f64::max);
+            if left < right {
+                (left, right - left)
+            } else {
+                (caret, search_fontsize as f64 / 2.0)
+            }
+        } else {
+            // The line is 2 pixels wide, a pixel after the character before it.
+            (caret, 3.0)
+        };
+
+        // Text that's too long for the box scrolls sideways to keep the cursor in it.
+        // The cursor sticks out past the end of the text when it's there, so that counts
+        // as part of the text, otherwise moving it off the end would nudge everything.
+        let text_room = rect[0] + rect[2] - TEXT_INSET - text_xpos;
+        let cursor_past_end = if box_cursor {
+            search_fontsize as f64 / 2.0
+        } else {
+            3.0
+        };
+        let scroll = keep_in_view(
+            self.scroll.get(),
+            cursor_offset..cursor_offset + cursor_width,
+            text_width + cursor_past_end,
+            text_room,
+        );
+        self.scroll.set(scroll);
+        let text_left = text_xpos;
+        text_xpos -= scroll;
+
+        // Whatever's scrolled out of the room for the text is cut off at its edges. The
+        // clip is a little wider than that so the box cursor's border doesn't get cut.
+        renderer.push_clip([text_left - 1.0, rect[1], text_room + 2.0, rect[3]]);
+
+        renderer.placed_text(
+            &placed,
+            foreground_color,
+            search_fontsize,
+            [text_xpos, text_ypos],
+        );
+
+        if box_cursor {
+            renderer.rectangle(
                 [
-                    // Add 5 pixels so we can have a nice comfortable gap
-                    // and the cursor isn't pressed up against the last character
-                    // in the buffer
-                    text_xpos + cursor_offset + 5.0,
-                    text_ypos - (SEARCH_FONTSIZE * 0.85),
-                    text_xpos + cursor_offset + 5.0,
-                    text_ypos + 2.0,
+                    text_xpos + cursor_offset,
+                    text_ypos - (search_fontsize as f64 * 0.85),
+                    cursor_width,
+                    search_fontsize as f64 * 0.85 + 2.0,
                 ],
-                c.transform,
-                g,
+                [0.0; 4],
+                Some((foreground_color, 0.5)),
+                0.0,
+            );
+        } else if show_cursor {
+            renderer.line(
+                // Add a pixel so the cursor isn't pressed up against the character
+                // before it
+                [
+                    text_xpos + cursor_offset + 1.0,
+                    text_ypos - (search_fontsize as f64 * 0.85),
+                ],
+                [text_xpos + cursor_offset + 1.0, text_ypos + 2.0],
+                foreground_color,
+                2.0,
             );
         }
+
+        renderer.pop_clip();
     }
 
     fn handle_event(&mut self, ev: &Event) {
         // We can get a string from the input very easily—no need to handle shift
-        // and all those messy thiings
-        if let Event::Input(inp, _) = ev {
-            match inp {
-                // We don't want to add the character to the text box if control is pressed,
-                // since we'll use Ctrl + key combinations for manipulating text in the text box.
-                Input::Text(add_string) if add_string != "" && !self.ctrl_pressed => {
-                    debug!("Add string is {}", add_string);
-                    self.buffer += add_string;
-                    self.events_run = true;
+        // and all those messy thiings. Text typed as part of a Ctrl/Alt shortcut never
+        // makes it here (see input.rs).
+        if let Event::Input(Input::Text(text), _) = ev {
+            if let Some(ref mut search) = self.history_search {
+                search.query.push_str(text);
+                self.find_in_history(self.history.len());
+            } else if !self.insert_mode {
+                for ch in text.chars() {
+                    self.normal_mode_command(ch);
                 }
+            } else if !text.is_empty() {
+                debug!("Add string is {}", text);
+                self.buffer.insert_str(self.cursor, text);
+                self.cursor += text.len();
+                self.events_run = true;
+                // Typing makes this a new line, so Alt+P starts from the newest
+                // search again.
+                self.history_index = None;
+            }
+        }
+    }
 
-                // This is for backspace (and ctrl-backspace since I'll implement that as well
-                // events.
-                Input::Button(ButtonArgs {
-                    button: Button::Keyboard(Key::Backspace),
-                    state: ButtonState::Press,
-                    ..
-                }) => {
-                    if !self.buffer.is_empty() {
-                        // We could probably consolidate this to get rid of the next_back twice
-                        // since both of these are double-ended iterators.
-
-                        // Use generics to save code. Both removals use a
-                        // DoubleEndedIterator, so we can captialize on that
-                        fn remove_last_iter_to_str<D>(mut de_iter: D) -> String
-                        where
-                            D: DoubleEndedIterator,
-                            String: FromIterator<<D as Iterator>::Item>,
-                        {
-                            de_iter.next_back();
-                            de_iter.collect()
-                        }
-                        self.buffer = if self.ctrl_pressed {
-                            // Take all the words except the last.                             let split_buffer =
-                            remove_last_iter_to_str(
-                                self.buffer
-                                    .split_whitespace()
-                                    .map(|word| word.to_owned() + " "),
-                            )
-                        } else {
-                            remove_last_iter_to_str(self.buffer.chars())
-                        };
-
-                        // We're going to reset the search bar if the string
-                        // becomes blank again through backspacing
-                        if self.buffer.is_empty() {
-                            self.events_run = false;
-                        }
-                    }
+    fn handle_action(&mut self, action: Action) {
+        // During a Ctrl+R search, only a few keys mean something to the search. The
+        // launcher finishes the search before sending us anything else.
+        if let Some(ref mut search) = self.history_search {
+            match action {
+                Action::HistorySearch => self.search_history(),
+                Action::DeleteBackward => {
+                    search.query.pop();
+                    search.found = None;
+                    self.find_in_history(self.history.len());
                 }
-                // We want to be able to manipulate the text in our buffer more easily
-                // This would do things like move cursor position and whatnot.
-                Input::Button(ButtonArgs {
-                    button: Button::Keyboard(key),
-                    state: ButtonState::Press,
-                    ..
-                }) if self.ctrl_pressed => match key {
-                    Key::K => self.buffer.clear(),
-                    _ => {}
-                },
-                // For control-backspace, which I absolutely cannot live without.
-                // We set ctrl_pressed so we can use it in conjunction with the backspace
-                // press to figure out when ctrl-backspace occurs.
-                Input::Button(ButtonArgs {
-                    button: Button::Keyboard(key),
-                    state,
-                    ..
-                }) if key == &Key::RCtrl || key == &Key::LCtrl => match state {
-                    ButtonState::Press => {
-                        self.ctrl_pressed = true;
-                    }
-                    ButtonState::Release => {
-                        self.ctrl_pressed = false;
-                    }
-                },
-                _ => {}
+                Action::Cancel => self.cancel_history_search(),
+                _ => self.finish_history_search(),
+            }
+            return;
+        }
+
+        match action {
+            Action::HistoryPrevious => self.history_previous(),
+            Action::HistoryNext => self.history_next(),
+            Action::HistorySearch => self.search_history(),
+            // Escape leaves insert mode, and like in vim the cursor goes back onto
+            // the char before it. In normal mode it cancels a half-typed dw/cw/dd.
+            Action::Cancel if self.modal => {
+                if self.insert_mode {
+                    self.insert_mode = false;
+                    self.cursor = self.previous_grapheme_boundary().unwrap_or(0);
+                }
+                self.pending_operator = None;
+            }
+            Action::CursorLeft => {
+                self.cursor = self.previous_grapheme_boundary().unwrap_or(self.cursor)
+            }
+            Action::CursorRight => {
+                self.cursor = self.next_grapheme_boundary().unwrap_or(self.cursor)
             }
+            Action::LineStart => self.cursor = 0,
+            Action::LineEnd => self.cursor = self.buffer.len(),
+            // Emacs word motion.
+            Action::WordLeft => self.cursor = self.previous_word_start(),
+            Action::WordRight => self.cursor = self.next_word_end(),
+            Action::DeleteBackward => {
+                if let Some(previous) = self.previous_grapheme_boundary() {
+                    self.delete_range(previous, self.cursor);
+                }
+            }
+            // For control-backspace, which I absolutely cannot live without.
+            Action::DeleteWordBackward => {
+                self.delete_range(self.previous_word_start(), self.cursor)
+            }
+            Action::DeleteForward => {
+                if let Some(next) = self.next_grapheme_boundary() {
+                    self.delete_range(self.cursor, next);
+                }
+            }
+            // Kill everything before the cursor...
+            Action::KillToStart => self.delete_range(0, self.cursor),
+            // ...or everything after it.
+            Action::KillLine => self.buffer.truncate(self.cursor),
+            _ => {}
+        }
+
+        // We're going to reset the search bar if the string
+        // becomes blank again through backspacing
+        if self.buffer.is_empty() {
+            self.events_run = false;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Keybindings, Keyboard, Route};
+    use crate::snapshot::{pressing, pressing_with, typing};
+
+    // A search bar behind the default keybindings, like it is in the launcher.
+    struct Typist {
+        search: Search,
+        keyboard: Keyboard,
+    }
+
+    impl Typist {
+        fn new(vi_mode: ViMode, word_chars: &str) -> Self {
+            Typist {
+                search: Search::new(vi_mode, word_chars),
+                keyboard: Keyboard::new(Keybindings::default()),
+            }
+        }
+
+        fn feed(&mut self, script: Vec<Event>) {
+            for ev in script {
+                match self.keyboard.route(&ev) {
+                    Route::Action(action) => self.search.handle_action(action),
+                    Route::Widgets => self.search.handle_event(&ev),
+                    Route::Nowhere => {}
+                }
+            }
+        }
+    }
+
+    impl std::ops::Deref for Typist {
+        type Target = Search;
+
+        fn deref(&self) -> &Search {
+            &self.search
+        }
+    }
+
+    impl std::ops::DerefMut for Typist {
+        fn deref_mut(&mut self) -> &mut Search {
+            &mut self.search
+        }
+    }
+
+    fn type_text(typist: &mut Typist, text: &str) {
+        typist.feed(typing(text));
+    }
+
+    fn press(typist: &mut Typist, pressed: Key) {
+        typist.feed(pressing(pressed));
+    }
+
+    fn press_with(typist: &mut Typist, modifier: Key, pressed: Key) {
+        typist.feed(pressing_with(modifier, pressed));
+    }
+
+    // The buffer with a | where the cursor is.
+    fn state(search: &Search) -> String {
+        let mut buffer = search.buffer.clone();
+        buffer.insert(search.cursor, '|');
+        buffer
+    }
+
+    #[test]
+    fn inserts_at_the_cursor() {
+        let mut search = Typist::new(ViMode::Off, "");
+        type_text(&mut search, "firefx");
+        press(&mut search, Key::Left);
+        type_text(&mut search, "o");
+        assert_eq!(state(&search), "firefo|x");
+        press(&mut search, Key::Home);
+        type_text(&mut search, "é");
+        assert_eq!(state(&search), "é|firefox");
+        press(&mut search, Key::End);
+        assert_eq!(state(&search), "éfirefox|");
+    }
+
+    #[test]
+    fn arrows_stop_at_the_ends() {
+        let mut search = Typist::new(ViMode::Off, "");
+        type_text(&mut search, "ab");
+        press(&mut search, Key::Right);
+        assert_eq!(state(&search), "ab|");
+        for _ in 0..3 {
+            press(&mut search, Key::Left);
+        }
+        assert_eq!(state(&search), "|ab");
+    }
+
+    #[test]
+    fn emacs_line_motion() {
+        let mut search = Typist::new(ViMode::Off, "");
+        type_text(&mut search, "code");
+        press_with(&mut search, Key::LCtrl, Key::A);
+        assert_eq!(state(&search), "|code");
+        press_with(&mut search, Key::RCtrl, Key::E);
+        assert_eq!(state(&search), "code|");
+    }
+
+    #[test]
+    fn word_motion() {
+        let mut search = Typist::new(ViMode::Off, "");
+        type_text(&mut search, "visual  studio-code");
+        press_with(&mut search, Key::LAlt, Key::B);
+        assert_eq!(state(&search), "visual  studio-|code");
+        press_with(&mut search, Key::LAlt, Key::B);
+        assert_eq!(state(&search), "visual  |studio-code");
+        press_with(&mut search, Key::LAlt, Key::B);
+        assert_eq!(state(&search), "|visual  studio-code");
+        press_with(&mut search, Key::LAlt, Key::F);
+        assert_eq!(state(&search), "visual|  studio-code");
+        press_with(&mut search, Key::LAlt, Key::F);
+        assert_eq!(state(&search), "visual  studio|-code");
+        // Alt+F shouldn't have typed an f.
+        assert_eq!(search.buffer, "visual  studio-code");
+    }
+
+    #[test]
+    fn backspace_and_forward_delete() {
+        let mut search = Typist::new(ViMode::Off, "");
+        type_text(&mut search, "gimp");
+        press(&mut search, Key::Left);
+        press(&mut search, Key::Left);
+        press(&mut search, Key::Backspace);
+        assert_eq!(state(&search), "g|mp");
+        press(&mut search, Key::Delete);
+        assert_eq!(state(&search), "g|p");
+        press_with(&mut search, Key::LCtrl, Key::D);
+        assert_eq!(state(&search), "g|");
+        // Nothing after the cursor to delete.
+        press(&mut search, Key::Delete);
+        assert_eq!(state(&search), "g|");
+    }
+
+    #[test]
+    fn kill_to_start_and_end() {
+        let mut search = Typist::new(ViMode::Off, "");
+        type_text(&mut search, "libreoffice");
+        for _ in 0..6 {
+            press(&mut search, Key::Left);
+        }
+        press_with(&mut search, Key::LCtrl, Key::K);
+        assert_eq!(state(&search), "libre|");
+        press(&mut search, Key::Left);
+        press_with(&mut search, Key::LCtrl, Key::U);
+        assert_eq!(state(&search), "|e");
+    }
+
+    #[test]
+    fn vi_escape_goes_to_normal_mode() {
+        let mut search = Typist::new(ViMode::Insert, "");
+        assert_eq!(search.mode_name(), Some("-- INSERT --"));
+        type_text(&mut search, "steam");
+        assert!(search.captures_escape());
+        press(&mut search, Key::Escape);
+        assert_eq!(search.mode_name(), Some("-- NORMAL --"));
+        assert_eq!(state(&search), "stea|m");
+        // Typing is commands now, so this doesn't go into the buffer.
+        type_text(&mut search, "h");
+        assert_eq!(state(&search), "ste|am");
+        assert!(!search.captures_escape());
+    }
+
+    #[test]
+    fn vi_motions() {
+        let mut search = Typist::new(ViMode::Normal, "");
+        search.set_buffer("gnome-system monitor");
+        type_text(&mut search, "0");
+        assert_eq!(state(&search), "|gnome-system monitor");
+        type_text(&mut search, "w");
+        assert_eq!(state(&search), "gnome|-system monitor");
+        type_text(&mut search, "w");
+        assert_eq!(state(&search), "gnome-|system monitor");
+        type_text(&mut search, "e");
+        assert_eq!(state(&search), "gnome-syste|m monitor");
+        type_text(&mut search, "w");
+        assert_eq!(state(&search), "gnome-system |monitor");
+        type_text(&mut search, "b");
+        assert_eq!(state(&search), "gnome-|system monitor");
+        type_text(&mut search, "$");
+        assert_eq!(state(&search), "gnome-system monito|r");
+        // l can't go past the last char in normal mode.
+        type_text(&mut search, "l");
+        assert_eq!(state(&search), "gnome-system monito|r");
+    }
+
+    #[test]
+    fn vi_operators() {
+        let mut search = Typist::new(ViMode::Normal, "");
+        search.set_buffer("open file manager");
+        type_text(&mut search, "0");
+        type_text(&mut search, "d");
+        type_text(&mut search, "w");
+        assert_eq!(state(&search), "|file manager");
+        type_text(&mut search, "x");
+        assert_eq!(state(&search), "|ile manager");
+        type_text(&mut search, "cw");
+        assert_eq!(state(&search), "| manager");
+        assert!(search.insert_mode);
+        type_text(&mut search, "task");
+        assert_eq!(state(&search), "task| manager");
+        press(&mut search, Key::Escape);
+        type_text(&mut search, "dd");
+        assert_eq!(state(&search), "|");
+    }
+
+    #[test]
+    fn vi_insert_commands() {
+        let mut search = Typist::new(ViMode::Normal, "");
+        search.set_buffer("vlc");
+        type_text(&mut search, "0");
+        type_text(&mut search, "a");
+        assert_eq!(state(&search), "v|lc");
+        press(&mut search, Key::Escape);
+        type_text(&mut search, "A");
+        assert_eq!(state(&search), "vlc|");
+        press(&mut search, Key::Escape);
+        type_text(&mut search, "I");
+        assert_eq!(state(&search), "|vlc");
+        press(&mut search, Key::Escape);
+        type_text(&mut search, "/");
+        assert_eq!(state(&search), "vlc|");
+        assert!(search.insert_mode);
+    }
+
+    #[test]
+    fn editing_works_on_grapheme_clusters() {
+        let mut search = Typist::new(ViMode::Off, "");
+        // A thumbs up with a skin tone, an e with a combining accent, and a family
+        // emoji made of four people joined with zero width joiners.
+        type_text(&mut search, "a\u{1f44d}\u{1f3fd}e\u{301}\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}\u{200d}\u{1f466}");
+        press(&mut search, Key::Backspace);
+        assert_eq!(search.buffer, "a\u{1f44d}\u{1f3fd}e\u{301}");
+        press(&mut search, Key::Left);
+        assert_eq!(state(&search), "a\u{1f44d}\u{1f3fd}|e\u{301}");
+        press(&mut search, Key::Left);
+        press(&mut search, Key::Delete);
+        assert_eq!(state(&search), "a|e\u{301}");
+        press(&mut search, Key::Right);
+        assert_eq!(state(&search), "ae\u{301}|");
+    }
+
+    #[test]
+    fn word_motion_uses_unicode_word_boundaries() {
+        let mut search = Typist::new(ViMode::Off, "");
+        type_text(&mut search, "can't open café.desktop");
+        // The . between letters doesn't split a word.
+        press_with(&mut search, Key::LAlt, Key::B);
+        assert_eq!(state(&search), "can't open |café.desktop");
+        press_with(&mut search, Key::LAlt, Key::B);
+        press_with(&mut search, Key::LAlt, Key::B);
+        assert_eq!(state(&search), "|can't open café.desktop");
+        press_with(&mut search, Key::LAlt, Key::F);
+        assert_eq!(state(&search), "can't| open café.desktop");
+    }
+
+    #[test]
+    fn ctrl_backspace_deletes_the_previous_word() {
+        let mut search = Typist::new(ViMode::Off, "");
+        type_text(&mut search, "open  ~/some-file.txt  ");
+        press_with(&mut search, Key::LCtrl, Key::Backspace);
+        // Spaces before the cursor go with the word, and the punctuation stops it
+        // (a . between letters is part of the word, though).
+        assert_eq!(state(&search), "open  ~/some-|");
+        press_with(&mut search, Key::LCtrl, Key::Backspace);
+        // The spaces between the words that are left are untouched.
+        assert_eq!(state(&search), "open  ~/|");
+        press_with(&mut search, Key::LCtrl, Key::Backspace);
+        assert_eq!(state(&search), "|");
+    }
+
+    #[test]
+    fn ctrl_backspace_only_deletes_before_the_cursor() {
+        let mut search = Typist::new(ViMode::Off, "");
+        type_text(&mut search, "foo bar baz");
+        for _ in 0..4 {
+            press(&mut search, Key::Left);
+        }
+        press_with(&mut search, Key::LCtrl, Key::Backspace);
+        assert_eq!(state(&search), "foo | baz");
+    }
+
+    #[test]
+    fn word_chars_join_words() {
+        let mut search = Typist::new(ViMode::Off, "-_");
+        type_text(&mut search, "run some-file_name");
+        press_with(&mut search, Key::LAlt, Key::B);
+        assert_eq!(state(&search), "run |some-file_name");
+        press_with(&mut search, Key::LAlt, Key::F);
+        assert_eq!(state(&search), "run some-file_name|");
+        press_with(&mut search, Key::LCtrl, Key::Backspace);
+        assert_eq!(state(&search), "run |");
+    }
+
+    #[test]
+    fn pasting_inserts_at_the_cursor_without_line_breaks() {
+        let mut search = Typist::new(ViMode::Off, "");
+        type_text(&mut search, "open ");
+        type_text(&mut search, "!");
+        press(&mut search, Key::Left);
+        search.insert("some\r\nfile.txt\n");
+        assert_eq!(state(&search), "open somefile.txt|!");
+    }
+
+    fn typist_with_history() -> Typist {
+        let mut search = Typist::new(ViMode::Off, "");
+        search.set_history(vec![
+            "firefox".to_string(),
+            "gimp".to_string(),
+            "fire".to_string(),
+        ]);
+        search
+    }
+
+    #[test]
+    fn alt_p_and_alt_n_walk_the_history() {
+        let mut search = typist_with_history();
+        type_text(&mut search, "draft");
+        press_with(&mut search, Key::LAlt, Key::P);
+        assert_eq!(state(&search), "fire|");
+        press_with(&mut search, Key::LAlt, Key::P);
+        press_with(&mut search, Key::LAlt, Key::P);
+        press_with(&mut search, Key::LAlt, Key::P);
+        assert_eq!(state(&search), "firefox|");
+        press_with(&mut search, Key::LAlt, Key::N);
+        assert_eq!(state(&search), "gimp|");
+        press_with(&mut search, Key::LAlt, Key::N);
+        press_with(&mut search, Key::LAlt, Key::N);
+        assert_eq!(state(&search), "draft|");
+    }
+
+    #[test]
+    fn ctrl_r_searches_backwards() {
+        let mut search = typist_with_history();
+        type_text(&mut search, "draft");
+        press_with(&mut search, Key::LCtrl, Key::R);
+        assert!(search.searching_history());
+        type_text(&mut search, "fi");
+        assert_eq!(state(&search), "|fire");
+        // Again finds the next older match, and stays on the last one when there's none.
+        press_with(&mut search, Key::LCtrl, Key::R);
+        assert_eq!(state(&search), "|firefox");
+        press_with(&mut search, Key::LCtrl, Key::R);
+        assert_eq!(state(&search), "|firefox");
+        type_text(&mut search, "x");
+        assert_eq!(
+            search.history_search_label().unwrap(),
+            "(failing reverse-i-search)`fix': "
+        );
+        press(&mut search, Key::Backspace);
+        assert_eq!(state(&search), "|fire");
+        // Escape puts back what was there.
+        press(&mut search, Key::Escape);
+        assert!(!search.searching_history());
+        assert_eq!(state(&search), "draft|");
+    }
+
+    #[test]
+    fn leaving_ctrl_r_keeps_the_match() {
+        let mut search = typist_with_history();
+        press_with(&mut search, Key::LCtrl, Key::R);
+        type_text(&mut search, "gi");
+        search.finish_history_search();
+        assert_eq!(state(&search), "|gimp");
+        // Alt+P goes on from the match, like in readline.
+        press_with(&mut search, Key::LAlt, Key::P);
+        assert_eq!(state(&search), "firefox|");
+    }
+
+    #[test]
+    fn scrolling_to_the_cursor() {
+        // Text that fits doesn't scroll.
+        assert_eq!(keep_in_view(0.0, 50.0..53.0, 80.0, 100.0), 0.0);
+        // Typing past the end scrolls just far enough to see the cursor.
+        assert_eq!(keep_in_view(0.0, 150.0..153.0, 153.0, 100.0), 53.0);
+        // Moving back a bit doesn't scroll, but moving off the start does.
+        assert_eq!(keep_in_view(53.0, 100.0..103.0, 153.0, 100.0), 53.0);
+        assert_eq!(keep_in_view(53.0, 20.0..23.0, 153.0, 100.0), 20.0);
+        // Deleting from the end pulls the text back so there's no gap after it.
+        assert_eq!(keep_in_view(53.0, 120.0..123.0, 123.0, 100.0), 23.0);
+    }
+}
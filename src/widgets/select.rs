@@ -1,17 +1,46 @@
 /* SPDX-License-Identifier: Zlib */
 
-use crate::configuration::{BACKGROUND_COLOR, FOREGROUND_COLOR};
+use crate::configuration::{Config, MouseAccept, ScrollMode, DEFAULT_LINES, DEFAULT_SCROLL_MARGIN};
+use crate::error::Error;
+use crate::input::Action;
+use crate::layout::{contains, elide, row_height, ELLIPSIS, TEXT_INSET};
+use crate::render::{Font, PlacedChar, Renderer};
+use crate::theme::{RowStyle, Theme};
 use crate::widgets::Widget;
-use gfx_device_gl::Resources;
-use log::warn;
 use piston_window::*;
+use std::cell::Cell;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+// The callback that's run when an entry is picked.
+pub type EntryCallback = Box<dyn Fn() -> Result<(), Error>>;
+
+// How many rows a notch of the scroll wheel moves with smooth scrolling.
+const WHEEL_ROWS: usize = 3;
+
+// The scrollbar handle doesn't get any shorter than this, so it's still something you
+// can see with thousands of entries.
+const MIN_HANDLE_HEIGHT: f64 = 12.0;
+
+// How quickly the second click has to come for it to be a double click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+
+// Entries can be marked so the theme draws them differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryState {
+    Normal,
+    Urgent,
+    Active,
+}
 
 pub struct Select {
     // These are the entries. The select box will
     // render these entry strings. When you press "Enter"
     // the callback function (closure) here will be called.
     // TODO maybe we want to convert this to a HashMap.
-    pub entries: Vec<(String, Box<dyn Fn() -> Result<(), String>>)>,
+    pub entries: Vec<(String, EntryCallback)>,
+    // The state of each entry in `entries`, at the same index.
+    entry_states: Vec<EntryState>,
     // This field filters the entries using String::contains to only have
     // entries whose strings contiain this String. An empty entry_filter
     // means that the entries won't be filtered.
@@ -30,22 +59,100 @@ pub struct Select {
     // if you selected the second entry in entries you'd have to find where the index '1' is
     // in filtered_entry_indices, which is annoying *and* inefficient).
     pub selected_entry: usize,
-    ctrl_pressed: bool,
+    // Where the mouse is, if it's over the window.
+    mouse_position: Option<[f64; 2]>,
+    // The entry (in the filtered indices, like selected_entry) that was clicked last,
+    // and when, to spot double clicks.
+    last_click: Option<(usize, Instant)>,
+    // Whether a click has to be a double click to run an entry.
+    mouse_accept: MouseAccept,
+    // See the options with the same names in configuration.rs.
+    scroll_mode: ScrollMode,
+    scroll_margin: usize,
+    wrap_around: bool,
+    // The first entry on screen with smooth scrolling. It only moves when the selection
+    // gets too close to an edge, so it depends on where it was before. It's updated
+    // when it's looked at, which happens in draw, hence the Cell.
+    scroll_offset: Cell<usize>,
+    // Scrolling that hasn't added up to a whole page yet. Touchpads scroll a little
+    // bit at a time.
+    scroll_amount: f64,
+    // Set when a click asks for the selected entry to be run. The launcher does the
+    // running, the same way it does for Enter.
+    accept_requested: bool,
+    // Where we go, how many rows fit and how tall they are, from the last arrange.
+    // Drawing and matching clicks to rows both go by these, so they always agree.
+    bounds: [f64; 4],
+    lines: usize,
+    row_height: f64,
 }
 
 impl Select {
-    pub fn new(entries: Vec<(String, Box<dyn Fn() -> Result<(), String>>)>) -> Self {
+    pub fn new(entries: Vec<(String, EntryCallback)>) -> Self {
         let filtered_entry_indices = (0..entries.len()).collect();
         Select {
+            entry_states: vec![EntryState::Normal; entries.len()],
             entries,
             filtered_entry_indices,
             entry_filter: None,
             // The first element will always be the one that's selected by default.
             selected_entry: 0,
-            ctrl_pressed: false,
+            mouse_position: None,
+            last_click: None,
+            mouse_accept: MouseAccept::default(),
+            scroll_mode: ScrollMode::default(),
+            scroll_margin: DEFAULT_SCROLL_MARGIN,
+            wrap_around: false,
+            scroll_offset: Cell::new(0),
+            scroll_amount: 0.0,
+            accept_requested: false,
+            bounds: [0.0; 4],
+            lines: DEFAULT_LINES,
+            row_height: 0.0,
+        }
+    }
+
+    // Pick up the options that change how the list behaves. This is done again when
+    // the config is reloaded.
+    pub fn configure(&mut self, config: &Config) {
+        self.mouse_accept = config.mouse_accept;
+        self.scroll_mode = config.scroll_mode;
+        self.scroll_margin = config.scroll_margin;
+        self.wrap_around = config.wrap_around;
+    }
+
+    // Whether a click asked to run the selected entry since the last call.
+    pub fn take_accept_request(&mut self) -> bool {
+        std::mem::take(&mut self.accept_requested)
+    }
+
+    // Run the callback for the selected entry. When the filter matches nothing,
+    // there's nothing to run, which isn't an error either.
+    pub fn accept(&self) -> Result<(), Error> {
+        let callback = self
+            .filtered_entry_indices
+            .get(self.selected_entry)
+            .and_then(|&index| self.entries.get(index))
+            .map(|(_, callback)| callback);
+        match callback {
+            Some(callback) => callback(),
+            None => Ok(()),
         }
     }
 
+    // Indices that are out of range are ignored, since they usually come from the
+    // command line and it's not worth crashing over.
+    pub fn set_entry_state(&mut self, index: usize, state: EntryState) {
+        if let Some(entry_state) = self.entry_states.get_mut(index) {
+            *entry_state = state;
+        }
+    }
+
+    #[cfg(test)]
+    pub fn entry_state(&self, index: usize) -> Option<EntryState> {
+        self.entry_states.get(index).copied()
+    }
+
     // We use this function to update the list of indices that are rendered.
     // We have a whole self.entries with the big list of entries, but when you
     // filter you need to know what to render. To do this, we use this function
@@ -83,143 +190,250 @@ impl Select {
         }
         // We want to make the list of indices to render in the list the full thing
         // if there is no filter. In other words, show everything.
-        else if let None = entry_filter {
+        else if entry_filter.is_none() {
             self.filtered_entry_indices = (0..self.entries.len()).collect();
         }
         // Finally, we
         self.entry_filter = entry_filter;
     }
+
+    // How many entries match the filter.
+    pub fn entry_count(&self) -> usize {
+        self.filtered_entry_indices.len()
+    }
+
+    // The text of the selected entry, if anything matches the filter.
+    pub fn selected_label(&self) -> Option<&str> {
+        let index = self.filtered_entry_indices.get(self.selected_entry)?;
+        self.entries.get(*index).map(|(label, _)| label.as_str())
+    }
+
+    // Move the selection up an entry. Obviously, we don't want to let the user go up
+    // beyond the first entry, unless wrap_around sends them to the last one.
+    pub fn select_previous(&mut self) {
+        if self.selected_entry > 0 {
+            self.selected_entry -= 1;
+        } else if self.wrap_around {
+            self.select_last();
+        }
+    }
+
+    // Same thing here—don't let the user go past the last entry.
+    pub fn select_next(&mut self) {
+        if self.selected_entry + 1 < self.filtered_entry_indices.len() {
+            self.selected_entry += 1;
+        } else if self.wrap_around {
+            self.selected_entry = 0;
+        }
+    }
+
+    fn select_last(&mut self) {
+        self.selected_entry = self.filtered_entry_indices.len().saturating_sub(1);
+    }
+
+    // Move the selection by `rows`, stopping at the ends. Paging doesn't wrap around,
+    // since jumping from the bottom of a long list to the top is never what you wanted
+    // when you held down Page Down.
+    fn move_selection(&mut self, rows: isize) {
+        let last_entry = self.filtered_entry_indices.len().saturating_sub(1);
+        self.selected_entry = if rows < 0 {
+            self.selected_entry.saturating_sub(rows.unsigned_abs())
+        } else {
+            (self.selected_entry + rows as usize).min(last_entry)
+        };
+    }
+
+    // The first entry on screen when there's room for `lines` of them.
+    fn first_visible_entry(&self, lines: usize) -> usize {
+        match self.scroll_mode {
+            // Handle overflow. When the selected index goes past the page, we have to get rid of the
+            // first page of items and replace those with the next page, and move the cursor to the top
+            // of the page. At least that's what Rofi does.
+            //
+            // By doing some integer division here, we only get skip indices every `lines` entries,
+            // which is exactly what we want.
+            ScrollMode::Page => (self.selected_entry / lines) * lines,
+            // Scroll just far enough to keep `scroll_margin` rows on each side of the
+            // selection. With only a few rows, the margin has to shrink so the selection
+            // still has somewhere to be.
+            ScrollMode::Smooth => {
+                let margin = self.scroll_margin.min((lines - 1) / 2);
+                let mut first = self.scroll_offset.get();
+                if self.selected_entry < first + margin {
+                    first = self.selected_entry.saturating_sub(margin);
+                }
+                if self.selected_entry + margin >= first + lines {
+                    first = self.selected_entry + margin + 1 - lines;
+                }
+                // There's no point in scrolling past the last entry and leaving empty
+                // rows at the bottom.
+                first = first.min(self.filtered_entry_indices.len().saturating_sub(lines));
+                self.scroll_offset.set(first);
+                first
+            }
+        }
+    }
+
+    // The entry (in the filtered indices) that's drawn at `position`. This is the same
+    // geometry draw uses.
+    fn entry_at(&self, position: [f64; 2]) -> Option<usize> {
+        // No rows fit (or we haven't been arranged yet), so there's nothing to hit.
+        if self.row_height <= 0.0 || !contains(self.bounds, position) {
+            return None;
+        }
+        let row = ((position[1] - self.bounds[1]) / self.row_height) as usize;
+        let entry = self.first_visible_entry(self.lines) + row;
+        if row < self.lines && entry < self.filtered_entry_indices.len() {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    // The entry under the mouse.
+    fn hovered_entry(&self) -> Option<usize> {
+        self.entry_at(self.mouse_position?)
+    }
+
+    fn click(&mut self) {
+        let entry = match self.hovered_entry() {
+            Some(entry) => entry,
+            None => return,
+        };
+        let double_click = matches!(
+            self.last_click,
+            Some((last_entry, time)) if last_entry == entry && time.elapsed() < DOUBLE_CLICK_TIME
+        );
+        self.selected_entry = entry;
+        if double_click || self.mouse_accept == MouseAccept::Click {
+            self.accept_requested = true;
+            self.last_click = None;
+        } else {
+            self.last_click = Some((entry, Instant::now()));
+        }
+    }
+
+    // With page scrolling, the wheel moves a page at a time, and the selection keeps its
+    // place on the page (or goes to the last entry if the last page isn't that long).
+    // With smooth scrolling it moves a few rows.
+    fn scroll(&mut self, amount: f64) {
+        let rows = match self.scroll_mode {
+            ScrollMode::Page => self.lines as isize,
+            ScrollMode::Smooth => WHEEL_ROWS as isize,
+        };
+        self.scroll_amount += amount;
+        while self.scroll_amount >= 1.0 {
+            self.scroll_amount -= 1.0;
+            self.move_selection(-rows);
+        }
+        while self.scroll_amount <= -1.0 {
+            self.scroll_amount += 1.0;
+            self.move_selection(rows);
+        }
+    }
 }
 
 impl Widget for Select {
+    // Keys come in as actions, so all that's left here is the mouse.
     fn handle_event(&mut self, ev: &piston_window::Event) {
-        if let Event::Input(input, _) = ev {
-            match input {
-                // Handle enter---execute the callback for the selected entry
-                Input::Button(ButtonArgs {
-                    button: Button::Keyboard(Key::Return),
-                    state: ButtonState::Press,
-                    ..
-                }) => {
-                    // We use get/expect to deliver our custom error message. Anyway, we can panic
-                    // here since the expect should never happen.
-                    let (_, callback) = self
-                        .entries
-                        .get(self.filtered_entry_indices[self.selected_entry])
-                        .expect("Couldn't fine call back for selected entry!");
-
-                    if let Err(msg) = callback() {
-                        warn!(
-                            "Callback for selected entry returned error message '{}'",
-                            msg
-                        );
-                    }
-                }
-                // Okay, here, I'm not actually too keen on coyping this code
-                // to keep things dry. I suppose I might write some kind of
-                // universal control handler in the future.
-                Input::Button(ButtonArgs {
-                    button: Button::Keyboard(key),
-                    state,
-                    ..
-                }) if key == &Key::RCtrl || key == &Key::LCtrl => match state {
-                    ButtonState::Press => {
-                        self.ctrl_pressed = true;
-                    }
-                    ButtonState::Release => {
-                        self.ctrl_pressed = false;
-                    }
-                },
-                // Handle up/down arrow keys (and we'll also add ctrl-n and ctrl-p for
-                // Emacs-esque handling here). We can use these to change
-                // the entry that's currently selected.
-                Input::Button(ButtonArgs {
-                    button: Button::Keyboard(key),
-                    state: ButtonState::Press,
-                    ..
-                }) => {
-                    // We match two keycodes to go up an entry: one is an up arrow key, as normal,
-                    // and the other is Ctrl-P, which is how you go up a line in Emacs (or even
-                    // Bash, but that's more "go up to the previous command").
-                    if !self.filtered_entry_indices.is_empty() {
-                        if ((*key == Key::P && self.ctrl_pressed)
-                        || *key == Key::Up) &&
-                    // Obviously, we don't want to let the user go up
-                    // beyond the first entry, so we have this condition.
-                        self.selected_entry > 0
-                        {
-                            self.selected_entry -= 1;
-                        }
-                        // We again do the same kind of keyboard-matching for the
-                        // obvious down-arrow keypress. The Emacs/Bash equivalent is Ctrl-N, so
-                        // we match that as well.
-                        else if ((*key == Key::N && self.ctrl_pressed)
-                        || *key == Key::Down) &&
-                    // Same thing here—don't let the user go past the
-                    // last entry.
-                        self.selected_entry < self.filtered_entry_indices.len() - 1
-                        {
-                            self.selected_entry += 1;
-                        }
-                    }
-                }
+        if let Some(position) = ev.mouse_cursor_args() {
+            self.mouse_position = Some(position);
+        }
+        if ev.cursor_args() == Some(false) {
+            self.mouse_position = None;
+        }
+        if let Some([_, amount]) = ev.mouse_scroll_args() {
+            // Scrolling up is positive.
+            self.scroll(amount);
+        }
+        if ev.press_args() == Some(Button::Mouse(MouseButton::Left)) {
+            self.click();
+        }
+    }
 
-                _ => {}
-            }
+    fn handle_action(&mut self, action: Action) {
+        match action {
+            // Up arrow or Ctrl-P, which is how you go up a line in Emacs (or even Bash,
+            // but that's more "go up to the previous command").
+            Action::MoveUp => self.select_previous(),
+            // And the down arrow or Ctrl-N.
+            Action::MoveDown => self.select_next(),
+            Action::PageUp => self.move_selection(-(self.lines as isize)),
+            Action::PageDown => self.move_selection(self.lines as isize),
+            Action::FirstEntry => self.selected_entry = 0,
+            Action::LastEntry => self.select_last(),
+            _ => {}
         }
     }
-    fn draw<G>(&self, coords: [f64; 2], c: &Context, g: &mut G, glyph_cache: &mut Glyphs)
-    where
-        G: Graphics<Texture = Texture<Resources>>,
-    {
-        const RECT_HEIGHT: f64 = 260.0;
-        const RECT_WIDTH: f64 = 700.0;
-
-        Rectangle::new_border([1.0, 1.0, 1.0, 1.0], 1.0)
-            .color([0.0, 0.0, 0.0, 0.0])
-            .draw(
-                [coords[0], coords[1], RECT_WIDTH, RECT_HEIGHT],
-                &Default::default(),
-                c.transform,
-                g,
-            );
 
-        // TODO we will make the maximum number of entries configurable.
-        const MAX_ENTRIES: f64 = 7.0; // Use an f64 to do simpler arithmetic later.
-        const ENTRY_HEIGHT: f64 = RECT_HEIGHT / MAX_ENTRIES;
+    // A row for each entry that matches, up to config.lines of them.
+    fn measure(&self, available: [f64; 2], config: &Config, _font: &Font) -> [f64; 2] {
+        let rows = self.entry_count().min(config.lines);
+        [
+            available[0],
+            (row_height(config) * rows as f64).min(available[1]),
+        ]
+    }
+
+    fn arrange(&mut self, rect: [f64; 4], config: &Config, _font: &Font) {
+        self.bounds = rect;
+        self.lines = config.lines;
+        self.row_height = row_height(config);
+    }
+
+    fn bounds(&self) -> [f64; 4] {
+        self.bounds
+    }
+
+    fn draw<R: Renderer>(&self, config: &Config, theme: &Theme, renderer: &mut R) {
+        let rect = self.bounds;
+        // When the window shrinks to fit the entries and nothing matches, there's no
+        // list at all, not even its border.
+        if rect[3] <= 0.0 {
+            return;
+        }
+
+        // The box's background goes under the rows, but the border goes on top of them,
+        // otherwise the selected row would paint over it.
+        renderer.rectangle(
+            rect,
+            theme.list.background.0,
+            None,
+            theme.list.border_radius,
+        );
+
+        let max_entries = self.lines;
+        let entry_height = self.row_height;
 
         // See my reasoning in search.rs to understanding why I used the letter 'A.'
         // I was going to use lazy_static to reuse this value between here and search.rs, but
         // it's not a great idea in my opinion, since the font sizes between here and
         // the search widget ~~may~~ differ, so it's easier to do just
         // calculate the character width again.
-        const LISTING_FONTSIZE: u32 = (RECT_HEIGHT / MAX_ENTRIES / 2.0) as u32;
-        let char_height = glyph_cache
-            .character(LISTING_FONTSIZE, 'A')
-            .expect("Failed to get max char height to vertically center text in the window!")
-            .top();
-
-        // We loop through each entry here to render the list.
-        // TODO handle an overflowing number of entries (pagination) using the
-        // self.selected_entry value to figure out where to start.
-        // That also means we don't have to iter through all of the entries
-        // on each draw.
-
-        // Handle overflow. When the selected index goes past the page, we have to get rid of the
-        // first 7 items and replace those with the next seven, and move the cursor to the top
-        // of the page. At least that's what Rofi does.
-        //
+        let listing_fontsize = config.font.size;
+        let char_height = renderer.font().glyph_top(listing_fontsize, 'A');
+
         // We'll figure out how many entries to skip.
-        //
-        // By doing some integer division here, we only get skip indices every 7 entries,
-        // which is exactly what we want.
-        let start_entries = (self.selected_entry / MAX_ENTRIES as usize) * MAX_ENTRIES as usize;
+        let start_entries = self.first_visible_entry(max_entries);
+
+        let hovered_entry = self.hovered_entry();
+
+        // Entries too long for the list are cut short to fit between the insets, and
+        // clear of the scrollbar if there is one.
+        let entry_count = self.filtered_entry_indices.len();
+        let mut text_room = rect[2] - 2.0 * TEXT_INSET;
+        if entry_count > max_entries {
+            text_room -= theme.scrollbar.width;
+        }
+
+        // With `ellipsis = "none"` they're cut off at the edge of the list instead.
+        renderer.push_clip(rect);
 
         for (index, entry_index) in self
             .filtered_entry_indices
             .iter()
             .skip(start_entries)
-            .take(MAX_ENTRIES as usize)
+            .take(max_entries)
             .enumerate()
         {
             // We get the entry from the index that's stored in the filtered_indices.
@@ -231,46 +445,69 @@ impl Widget for Select {
             // It's easier to store this in a variable since we reuse this value multiple times.
             let selected_entry = start_entries + index == self.selected_entry;
 
+            let row_style = if selected_entry {
+                theme.rows.selected
+            } else {
+                match self.entry_states[*entry_index] {
+                    EntryState::Urgent => theme.rows.urgent,
+                    EntryState::Active => theme.rows.active,
+                    EntryState::Normal => match theme.rows.alternate_background {
+                        Some(background) if (start_entries + index) % 2 == 1 => RowStyle {
+                            background,
+                            ..theme.rows.normal
+                        },
+                        _ => theme.rows.normal,
+                    },
+                }
+            };
+
             // Move the index so that we actually start at the right index
             // We'll calculate the y-coordinates of the line since we'll use that to
             // calculate where to position text.
-            let entry_line_ypos = coords[1] + (ENTRY_HEIGHT * ((index + 1) as f64));
+            let entry_line_ypos = rect[1] + (entry_height * ((index + 1) as f64));
 
-            // Draw line
-            line(
-                FOREGROUND_COLOR,
-                1.0,
+            // We're going to invert the color of the text and the entry if this is the
+            // selected entry (at least in the default theme). To color the entry, we use a rectangle.
+            renderer.rectangle(
                 [
-                    // The line should span the entire stretch of the window.
-                    coords[0],
-                    // Y-position of the line is 1/8th of the height of the window.
-                    entry_line_ypos,
-                    coords[0] + RECT_WIDTH,
-                    entry_line_ypos,
+                    rect[0],
+                    // We have to subtract here since the line is the bottom
+                    // right of the "rectangle," but the rectangle here is
+                    // drawn from the top-left.
+                    entry_line_ypos - entry_height,
+                    rect[2],
+                    entry_height,
                 ],
-                c.transform,
-                g,
+                row_style.background.0,
+                None,
+                0.0,
             );
-
-            // We're going to invert the color of the text and the entry if this is the
-            // selected entry. To invert the color of the entry, we use a rectangle.
-            if selected_entry {
-                rectangle(
-                    FOREGROUND_COLOR,
-                    [
-                        coords[0],
-                        // We have to subtract here since the line is the bottom
-                        // right of the "rectangle," but the rectangle here is
-                        // drawn from the top-left.
-                        entry_line_ypos - ENTRY_HEIGHT,
-                        RECT_WIDTH,
-                        ENTRY_HEIGHT,
-                    ],
-                    c.transform,
-                    g,
-                );
+            if let Some(hover_background) = theme.rows.hover_background {
+                if hovered_entry == Some(start_entries + index) && !selected_entry {
+                    renderer.rectangle(
+                        [
+                            rect[0],
+                            entry_line_ypos - entry_height,
+                            rect[2],
+                            entry_height,
+                        ],
+                        hover_background.0,
+                        None,
+                        0.0,
+                    );
+                }
             }
 
+            // Draw line
+            renderer.line(
+                // The line should span the entire stretch of the window.
+                // Y-position of the line is 1/8th of the height of the window.
+                [rect[0], entry_line_ypos],
+                [rect[0] + rect[2], entry_line_ypos],
+                theme.list.separator_color.0,
+                2.0,
+            );
+
             // Just like the search bar, we want to be 15 pixels from the left edge
             // of the box we're drawing in.
             // The y-position is calculated also like with the search bar, but
@@ -279,30 +516,315 @@ impl Widget for Select {
             // (Haha, trying to appear like I'm following DRY
             // by changing text_xpos to entry_text_xpos but this code is not
             // very DRY-esque here)
-            let entry_text_xpos = coords[0] + 15.0;
-            let entry_text_ypos = entry_line_ypos - (ENTRY_HEIGHT / 2.0) + (char_height / 2.0);
-
-            text::Text::new_color(
-                if selected_entry {
-                    // We make the text the colour of the background when it's selected,
-                    // since when it's selected, the entry will be white and white text
-                    // on a white background won't be visible.
-                    BACKGROUND_COLOR
+            let entry_text_xpos = rect[0] + TEXT_INSET;
+            let entry_text_ypos = entry_line_ypos - (entry_height / 2.0) + (char_height / 2.0);
+
+            // We draw the part of the entry that matches the search separately so
+            // the theme can mark it.
+            let match_range = self
+                .entry_filter
+                .as_ref()
+                .and_then(|filter| match_range(entry, filter))
+                .unwrap_or(0..0);
+            let highlight_color = match theme.highlight.foreground {
+                // The selected row has its own colors, so a highlight color picked to
+                // stand out against normal rows might not be readable there.
+                Some(color) if !selected_entry => color.0,
+                _ => row_style.foreground.0,
+            };
+
+            // The start and the end of the entry that fit, with the ellipsis in between
+            // if anything was cut out. That's laid out all at once, so right-to-left text
+            // comes out in the right order, and then the match is drawn in its own
+            // color. The match can be cut too, and then only what's left of it is
+            // highlighted.
+            let (head, tail) = elide(
+                renderer.font(),
+                listing_fontsize,
+                entry,
+                text_room,
+                config.ellipsis,
+            );
+            let mut shown = entry[head.clone()].to_string();
+            if head.end < tail.start {
+                shown.push_str(ELLIPSIS);
+            }
+            let tail_offset = shown.len();
+            shown.push_str(&entry[tail.clone()]);
+            // Whether something in `shown` is part of the match in the entry. The
+            // ellipsis isn't part of anything.
+            let is_matched = |place: &PlacedChar| {
+                let index = if place.index < head.end {
+                    place.index
+                } else if place.index >= tail_offset {
+                    place.index - tail_offset + tail.start
                 } else {
-                    FOREGROUND_COLOR
-                },
-                LISTING_FONTSIZE as u32 * 2,
-            )
-            .draw(
-                &entry,
-                glyph_cache,
-                &DrawState::default(),
-                c.transform
-                    .trans(entry_text_xpos, entry_text_ypos)
-                    .zoom(0.5),
-                g,
-            )
-            .unwrap();
+                    return false;
+                };
+                match_range.contains(&index)
+            };
+
+            let (placed, _) = renderer.font().layout(listing_fontsize, &shown);
+            let (matched, rest): (Vec<PlacedChar>, Vec<PlacedChar>) =
+                placed.iter().partition(|place| is_matched(place));
+            let position = [entry_text_xpos, entry_text_ypos];
+            renderer.placed_text(&rest, row_style.foreground.0, listing_fontsize, position);
+            renderer.placed_text(&matched, highlight_color, listing_fontsize, position);
+
+            // With bidi text, the match can end up in more than one piece, and each
+            // one gets its own underline.
+            if theme.highlight.underline {
+                for piece in placed.split(|place| !is_matched(place)) {
+                    if let (Some(first), Some(last)) = (piece.first(), piece.last()) {
+                        renderer.line(
+                            [entry_text_xpos + first.x, entry_text_ypos + 3.0],
+                            [entry_text_xpos + last.x + last.width, entry_text_ypos + 3.0],
+                            highlight_color,
+                            1.0,
+                        );
+                    }
+                }
+            }
+        }
+
+        renderer.pop_clip();
+
+        // We only need a scrollbar if the entries don't all fit.
+        if entry_count > max_entries {
+            let scrollbar_xpos = rect[0] + rect[2] - theme.scrollbar.width;
+            renderer.rectangle(
+                [scrollbar_xpos, rect[1], theme.scrollbar.width, rect[3]],
+                theme.scrollbar.track.0,
+                None,
+                0.0,
+            );
+            // The handle is as long as the part of the list that's on screen, and it's
+            // at the top when the first entry is on screen and at the bottom when the last
+            // one is. (With page scrolling, the last page can start past
+            // entry_count - max_entries, which is why we clamp.)
+            let handle_height =
+                (rect[3] * max_entries as f64 / entry_count as f64).max(MIN_HANDLE_HEIGHT);
+            let position = (start_entries as f64 / (entry_count - max_entries) as f64).min(1.0);
+            renderer.rectangle(
+                [
+                    scrollbar_xpos,
+                    rect[1] + (rect[3] - handle_height) * position,
+                    theme.scrollbar.width,
+                    handle_height,
+                ],
+                theme.scrollbar.handle.0,
+                None,
+                0.0,
+            );
         }
+
+        renderer.rectangle(
+            rect,
+            [0.0; 4],
+            Some((theme.list.border_color.0, theme.list.border_width)),
+            theme.list.border_radius,
+        );
+    }
+}
+
+// Find the part of `entry` that `filter` matched, using the same case-insensitive
+// comparison as update_entry_filter. We can't just search in the lowercase entry, since
+// lowercasing can change the length of a string and the range has to be in `entry`.
+fn match_range(entry: &str, filter: &str) -> Option<Range<usize>> {
+    let filter = filter.to_lowercase();
+    if filter.is_empty() {
+        return None;
+    }
+
+    entry.char_indices().find_map(|(start, _)| {
+        let rest = &entry[start..];
+        if !rest.to_lowercase().starts_with(&filter) {
+            return None;
+        }
+        // The match ends at the first char boundary where we've covered the whole filter.
+        rest.char_indices()
+            .map(|(end, ch)| end + ch.len_utf8())
+            .find(|&end| rest[..end].to_lowercase().len() >= filter.len())
+            .map(|end| start..start + end)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A list of `count` entries, drawn at 40, 200 with 5 rows of 52 pixels each.
+    fn drawn_select(count: usize) -> Select {
+        let entries = (0..count)
+            .map(|i| {
+                let callback: EntryCallback = Box::new(|| Ok(()));
+                (format!("entry {}", i), callback)
+            })
+            .collect();
+        let mut select = Select::new(entries);
+        select.bounds = [40.0, 200.0, 700.0, 260.0];
+        select.lines = 5;
+        select.row_height = 52.0;
+        select
+    }
+
+    fn move_to(select: &mut Select, position: [f64; 2]) {
+        select.handle_event(&Event::Input(
+            Input::Move(Motion::MouseCursor(position)),
+            None,
+        ));
+    }
+
+    fn click(select: &mut Select) {
+        select.handle_event(&Event::Input(
+            Input::Button(ButtonArgs {
+                state: ButtonState::Press,
+                button: Button::Mouse(MouseButton::Left),
+                scancode: None,
+            }),
+            None,
+        ));
+    }
+
+    fn scroll(select: &mut Select, amount: f64) {
+        select.handle_event(&Event::Input(
+            Input::Move(Motion::MouseScroll([0.0, amount])),
+            None,
+        ));
+    }
+
+    #[test]
+    fn rows_are_hit_where_they_are_drawn() {
+        let mut select = drawn_select(8);
+        move_to(&mut select, [100.0, 201.0]);
+        assert_eq!(select.hovered_entry(), Some(0));
+        move_to(&mut select, [100.0, 200.0 + 52.0 * 3.5]);
+        assert_eq!(select.hovered_entry(), Some(3));
+        // Off to the side, and above the list.
+        move_to(&mut select, [39.0, 201.0]);
+        assert_eq!(select.hovered_entry(), None);
+        move_to(&mut select, [100.0, 199.0]);
+        assert_eq!(select.hovered_entry(), None);
+        // The second page only has three entries on it.
+        select.selected_entry = 5;
+        move_to(&mut select, [100.0, 200.0 + 52.0 * 2.5]);
+        assert_eq!(select.hovered_entry(), Some(7));
+        move_to(&mut select, [100.0, 200.0 + 52.0 * 3.5]);
+        assert_eq!(select.hovered_entry(), None);
+    }
+
+    #[test]
+    fn rows_with_no_height_are_never_hit() {
+        let mut select = drawn_select(8);
+        select.row_height = 0.0;
+        move_to(&mut select, [100.0, 201.0]);
+        assert_eq!(select.hovered_entry(), None);
+        // Where the top of the list is the bottom of the window.
+        select.bounds[3] = 0.0;
+        move_to(&mut select, [100.0, 200.0]);
+        assert_eq!(select.hovered_entry(), None);
+    }
+
+    #[test]
+    fn click_selects_and_double_click_accepts() {
+        let mut select = drawn_select(8);
+        move_to(&mut select, [100.0, 200.0 + 52.0 * 2.5]);
+        click(&mut select);
+        assert_eq!(select.selected_entry, 2);
+        assert!(!select.take_accept_request());
+        click(&mut select);
+        assert!(select.take_accept_request());
+        // Clicking somewhere that isn't an entry doesn't do anything.
+        move_to(&mut select, [10.0, 10.0]);
+        click(&mut select);
+        assert_eq!(select.selected_entry, 2);
+        assert!(!select.take_accept_request());
+    }
+
+    #[test]
+    fn single_click_accepts_if_configured() {
+        let mut select = drawn_select(8);
+        select.mouse_accept = MouseAccept::Click;
+        move_to(&mut select, [100.0, 200.0 + 52.0 * 1.5]);
+        click(&mut select);
+        assert_eq!(select.selected_entry, 1);
+        assert!(select.take_accept_request());
+    }
+
+    #[test]
+    fn wheel_moves_a_page() {
+        let mut select = drawn_select(12);
+        select.selected_entry = 1;
+        scroll(&mut select, -1.0);
+        assert_eq!(select.selected_entry, 6);
+        // A touchpad scrolls a bit at a time.
+        scroll(&mut select, -0.5);
+        assert_eq!(select.selected_entry, 6);
+        scroll(&mut select, -0.5);
+        assert_eq!(select.selected_entry, 11);
+        scroll(&mut select, -1.0);
+        assert_eq!(select.selected_entry, 11);
+        scroll(&mut select, 3.0);
+        assert_eq!(select.selected_entry, 0);
+    }
+
+    #[test]
+    fn smooth_scrolling_keeps_a_margin() {
+        let mut select = drawn_select(20);
+        select.scroll_mode = ScrollMode::Smooth;
+        select.scroll_margin = 1;
+        // Rows 0-4 are on screen until the selection gets to row 4.
+        for _ in 0..3 {
+            select.select_next();
+        }
+        assert_eq!(select.first_visible_entry(5), 0);
+        select.select_next();
+        assert_eq!(select.first_visible_entry(5), 1);
+        // Going back up doesn't scroll until the selection is at the top margin.
+        select.select_previous();
+        select.select_previous();
+        assert_eq!(select.first_visible_entry(5), 1);
+        select.select_previous();
+        assert_eq!(select.first_visible_entry(5), 0);
+        // The last entry sits on the bottom row, not in the middle of empty ones.
+        select.handle_action(Action::LastEntry);
+        assert_eq!(select.first_visible_entry(5), 15);
+    }
+
+    #[test]
+    fn page_keys_and_ends() {
+        let mut select = drawn_select(12);
+        select.handle_action(Action::PageDown);
+        assert_eq!(select.selected_entry, 5);
+        select.handle_action(Action::PageDown);
+        select.handle_action(Action::PageDown);
+        assert_eq!(select.selected_entry, 11);
+        select.handle_action(Action::PageUp);
+        assert_eq!(select.selected_entry, 6);
+        select.handle_action(Action::FirstEntry);
+        assert_eq!(select.selected_entry, 0);
+        select.handle_action(Action::LastEntry);
+        assert_eq!(select.selected_entry, 11);
+    }
+
+    #[test]
+    fn wrap_around_at_the_ends() {
+        let mut select = drawn_select(3);
+        select.handle_action(Action::MoveUp);
+        assert_eq!(select.selected_entry, 0);
+        select.wrap_around = true;
+        select.handle_action(Action::MoveUp);
+        assert_eq!(select.selected_entry, 2);
+        select.handle_action(Action::MoveDown);
+        assert_eq!(select.selected_entry, 0);
+    }
+
+    #[test]
+    fn accepting_with_nothing_matching() {
+        let mut select = drawn_select(3);
+        select.update_entry_filter(Some("nothing like it".to_string()));
+        assert_eq!(select.entry_count(), 0);
+        // This used to index past the end of the filtered entries.
+        assert!(select.accept().is_ok());
     }
 }
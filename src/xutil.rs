@@ -0,0 +1,196 @@
+/* SPDX-License-Identifier: Zlib */
+
+// Small helpers for talking to the X server through the connection winit already
+// opened for our window. Everything in here is unsafe Xlib underneath, so it's
+// kept in one place instead of sprinkled through the rest of the code.
+
+use std::convert::TryInto;
+use std::ffi::CString;
+use std::os::raw::{c_int, c_uchar, c_ulong};
+use std::ptr;
+use winit::platform::unix::x11::{ffi, XConnection};
+
+pub fn intern_atom(xconn: &XConnection, name: &str) -> ffi::Atom {
+    let name = CString::new(name).expect("Atom names can't have NUL bytes in them");
+    unsafe { (xconn.xlib.XInternAtom)(xconn.display, name.as_ptr(), ffi::False) }
+}
+
+pub fn root_window(xconn: &XConnection) -> ffi::Window {
+    unsafe { (xconn.xlib.XDefaultRootWindow)(xconn.display) }
+}
+
+// Read a whole property off of a window, and delete it afterwards if `delete` is set.
+// Returns the property's type, its format (8, 16 or 32 bits per item) and the raw
+// items. Note that Xlib hands back format-32 items as C longs, which are 64 bits wide
+// on 64-bit machines.
+pub fn get_property(
+    xconn: &XConnection,
+    window: ffi::Window,
+    property: ffi::Atom,
+    requested_type: ffi::Atom,
+    delete: bool,
+) -> Option<(ffi::Atom, c_int, Vec<u8>)> {
+    let mut actual_type: ffi::Atom = 0;
+    let mut actual_format: c_int = 0;
+    let mut item_count: c_ulong = 0;
+    let mut bytes_after: c_ulong = 0;
+    let mut data: *mut c_uchar = ptr::null_mut();
+
+    let status = unsafe {
+        (xconn.xlib.XGetWindowProperty)(
+            xconn.display,
+            window,
+            property,
+            0,
+            // This is in 32-bit units, and it's the most we'll ever read at once.
+            // Properties bigger than this are transferred with INCR anyway.
+            c_int::MAX as _,
+            if delete { ffi::True } else { ffi::False },
+            requested_type,
+            &mut actual_type,
+            &mut actual_format,
+            &mut item_count,
+            &mut bytes_after,
+            &mut data,
+        )
+    };
+    if status != ffi::Success as c_int || data.is_null() {
+        return None;
+    }
+
+    let item_size = match actual_format {
+        8 => 1,
+        16 => std::mem::size_of::<std::os::raw::c_short>(),
+        32 => std::mem::size_of::<std::os::raw::c_long>(),
+        _ => 0,
+    };
+    let bytes =
+        unsafe { std::slice::from_raw_parts(data, item_count as usize * item_size) }.to_vec();
+    unsafe { (xconn.xlib.XFree)(data as *mut _) };
+
+    if actual_type == 0 {
+        // The property doesn't exist.
+        return None;
+    }
+    Some((actual_type, actual_format, bytes))
+}
+
+// The contents of the RESOURCE_MANAGER property on the root window, which is where
+// `xrdb` puts everything from ~/.Xresources. We read it ourselves instead of using
+// XResourceManagerString, since that one is a copy from when the display was opened.
+pub fn resource_manager(xconn: &XConnection) -> Option<String> {
+    let property = intern_atom(xconn, "RESOURCE_MANAGER");
+    let (_, format, bytes) =
+        get_property(xconn, root_window(xconn), property, ffi::XA_STRING, false)?;
+    if format != 8 {
+        return None;
+    }
+    // The property is Latin-1, but everything we care about in it is ASCII.
+    let bytes = bytes.split(|&b| b == 0).next().unwrap_or_default();
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+// Where the mouse pointer is, on the whole screen.
+pub fn pointer_position(xconn: &XConnection) -> Option<[i32; 2]> {
+    let (mut root, mut child) = (0, 0);
+    let (mut x, mut y, mut window_x, mut window_y) = (0, 0, 0, 0);
+    let mut mask = 0;
+    let on_screen = unsafe {
+        (xconn.xlib.XQueryPointer)(
+            xconn.display,
+            root_window(xconn),
+            &mut root,
+            &mut child,
+            &mut x,
+            &mut y,
+            &mut window_x,
+            &mut window_y,
+            &mut mask,
+        )
+    };
+    // It's False when the pointer is on another screen (not monitor) entirely.
+    if on_screen == ffi::False {
+        return None;
+    }
+    Some([x, y])
+}
+
+// Where the window the window manager says is focused is on the whole screen, and how
+// big it is: x, y, width, height. This goes by _NET_ACTIVE_WINDOW, which every EWMH
+// window manager keeps up to date. It's None without one, or with nothing focused.
+pub fn active_window_geometry(xconn: &XConnection) -> Option<[i32; 4]> {
+    let property = intern_atom(xconn, "_NET_ACTIVE_WINDOW");
+    let root = root_window(xconn);
+    let (_, format, bytes) = get_property(xconn, root, property, ffi::XA_WINDOW, false)?;
+    if format != 32 {
+        return None;
+    }
+    // A format-32 item is a C long (see get_property).
+    let item = bytes.get(..std::mem::size_of::<c_ulong>())?;
+    let window = c_ulong::from_ne_bytes(item.try_into().ok()?);
+    if window == 0 {
+        return None;
+    }
+
+    let mut attributes: ffi::XWindowAttributes = unsafe { std::mem::zeroed() };
+    let (mut x, mut y, mut child) = (0, 0, 0);
+    unsafe {
+        if (xconn.xlib.XGetWindowAttributes)(xconn.display, window, &mut attributes) == 0 {
+            return None;
+        }
+        // The window's position in the attributes is inside its parent, which is
+        // usually the window manager's frame, so we ask where it is on the root window.
+        if (xconn.xlib.XTranslateCoordinates)(
+            xconn.display,
+            window,
+            root,
+            0,
+            0,
+            &mut x,
+            &mut y,
+            &mut child,
+        ) == ffi::False
+        {
+            return None;
+        }
+    }
+    Some([x, y, attributes.width, attributes.height])
+}
+
+// Grab the pointer for `window`, so we hear about clicks outside of it too. With
+// owner_events set, everything over our own window still arrives like usual, and
+// everything else arrives as if it were on our window, at positions outside of it.
+//
+// winit reads the pointer through XInput2, so a core XGrabPointer would take the
+// events away from it. We have to grab the XInput2 device instead.
+pub fn grab_pointer(xconn: &XConnection, window: ffi::Window) -> bool {
+    let mut device_id: c_int = 0;
+    if unsafe { (xconn.xinput2.XIGetClientPointer)(xconn.display, 0, &mut device_id) } == 0 {
+        return false;
+    }
+    // The same events winit selects for the pointer on its windows.
+    let mask = ffi::XI_ButtonPressMask
+        | ffi::XI_ButtonReleaseMask
+        | ffi::XI_MotionMask
+        | ffi::XI_EnterMask
+        | ffi::XI_LeaveMask;
+    let mut event_mask = ffi::XIEventMask {
+        deviceid: device_id,
+        mask: &mask as *const _ as *mut c_uchar,
+        mask_len: std::mem::size_of_val(&mask) as c_int,
+    };
+    let status = unsafe {
+        (xconn.xinput2.XIGrabDevice)(
+            xconn.display,
+            device_id,
+            window,
+            ffi::CurrentTime,
+            0,
+            ffi::XIGrabModeAsync,
+            ffi::XIGrabModeAsync,
+            ffi::True,
+            &mut event_mask,
+        )
+    };
+    status == ffi::GrabSuccess
+}